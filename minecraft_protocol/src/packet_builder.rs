@@ -35,6 +35,11 @@ impl PacketBuilder {
         self
     }
 
+    /// VarInt length followed by the bytes
+    pub fn write_byte_array(self, buf: &[u8]) -> Self {
+        self.write_var_int(VarInt(buf.len() as i32)).write_buffer(buf)
+    }
+
     pub fn write_int<I: Integer>(mut self, int: I) -> Self {
         self.data.extend(int.to_bytes());
         self
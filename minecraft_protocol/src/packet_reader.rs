@@ -29,6 +29,20 @@ impl PacketReader {
         String::from_utf8(stream).map_err(|e| Error::new(ErrorKind::InvalidData, e))
     }
 
+    /// VarInt length followed by that many bytes
+    pub async fn read_byte_array(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.read_var_int().await?.0;
+        let remaining = self.stream.get_ref().len() as u64 - self.stream.position();
+        if len < 0 || len as u64 > remaining {
+            return Err(Error::new(ErrorKind::InvalidData, "Bad byte array length"));
+        }
+
+        let mut buf = vec![0; len as usize];
+        self.stream.read_exact(&mut buf)?;
+
+        Ok(buf)
+    }
+
     pub fn read_int<T: Integer>(&mut self) -> io::Result<T> {
         let mut buf = vec![0; T::byte_len()];
         self.stream.read_exact(&mut buf)?;
@@ -50,4 +64,12 @@ impl PacketReader {
     pub fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.stream.read(buf)
     }
+
+    /// Everything left in the packet, for trailing byte arrays without a length
+    pub fn read_remaining(&mut self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.stream.read_to_end(&mut buf)?;
+
+        Ok(buf)
+    }
 }
@@ -0,0 +1,255 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use mongodb::bson::DateTime;
+use serde_json::{json, Map};
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::{
+    fingerprint::classify_bedrock,
+    model::{Description, Players, ServerRecord, StatusResponse, Version},
+    utils::unspecified_addr,
+};
+
+pub const DEFAULT_PORT: u16 = 19132;
+
+/// https://wiki.vg/Raknet_Protocol#Data_types
+const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// PacketID 0x01
+const UNCONNECTED_PING: u8 = 0x01;
+/// PacketID 0x1c
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// Record for a server that answered `status`, there's nothing else to probe
+pub fn get_full_info(addr: SocketAddr, status: StatusResponse) -> ServerRecord {
+    ServerRecord {
+        ip: addr.ip().to_string(),
+        port: addr.port(),
+        address: addr.to_string(),
+        hostname: None,
+        edition: "bedrock".to_string(),
+        license: None,
+        auth: None,
+        disconnect_reason: None,
+        login_channels: None,
+        public_key: None,
+        public_keys: Vec::new(),
+        first_seen: None,
+        last_seen: Some(DateTime::now()),
+        players: Vec::new(),
+        motd: Some(status.description.plain()),
+        favicon: None,
+        loader: None,
+        mods: None,
+        software: Some(classify_bedrock(&status)),
+        status,
+        query: None,
+    }
+}
+
+/// https://wiki.vg/Raknet_Protocol#Unconnected_Ping
+pub async fn get_status(addr: SocketAddr) -> Result<StatusResponse> {
+    let socket = UdpSocket::bind(unspecified_addr(&addr)).await?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let mut ping = vec![UNCONNECTED_PING];
+    ping.extend(time.to_be_bytes());
+    ping.extend(MAGIC);
+    ping.extend(rand::random::<u64>().to_be_bytes());
+
+    socket.send_to(&ping, addr).await?;
+
+    let mut buf = [0; 1500];
+    let (len, _) = timeout(Duration::from_secs(3), socket.recv_from(&mut buf))
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "No pong"))??;
+
+    parse_pong(&buf[..len])
+}
+
+/// PacketID(1) + Time(8) + ServerGUID(8) + Magic(16) + StrLen(2) + MOTD
+fn parse_pong(pong: &[u8]) -> Result<StatusResponse> {
+    let invalid = |msg| Error::new(ErrorKind::InvalidData, msg);
+
+    if pong.len() < 35 || pong[0] != UNCONNECTED_PONG {
+        return Err(invalid("Not an unconnected pong"));
+    }
+    if pong[17..33] != MAGIC {
+        return Err(invalid("Bad magic"));
+    }
+
+    let len = u16::from_be_bytes([pong[33], pong[34]]) as usize;
+    let motd = pong
+        .get(35..35 + len)
+        .ok_or_else(|| invalid("Truncated MOTD"))?;
+
+    parse_motd(&String::from_utf8_lossy(motd))
+}
+
+/// `edition;motd;protocol;version;online;max;guid;level name;gamemode;gamemode id;port v4;port v6;`
+///
+/// Older servers stop after the player counts, so everything after those is optional.
+fn parse_motd(motd: &str) -> Result<StatusResponse> {
+    let fields: Vec<&str> = motd.split(';').collect();
+    if fields.len() < 6 {
+        return Err(Error::new(ErrorKind::InvalidData, "Invalid MOTD"));
+    }
+
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+    let number = |i: usize| field(i).parse::<i64>().unwrap_or(-1);
+
+    let description = match field(7) {
+        "" => field(1).to_string(),
+        level_name => format!("{}\n{}", field(1), level_name),
+    };
+
+    let extra = [
+        ("edition", json!(field(0))),
+        ("serverGuid", json!(field(6))),
+        ("levelName", json!(field(7))),
+        ("gamemode", json!(field(8))),
+        ("gamemodeId", json!(number(9))),
+        ("portV4", json!(number(10))),
+        ("portV6", json!(number(11))),
+    ];
+
+    Ok(StatusResponse {
+        version: Version {
+            name: field(3).to_string(),
+            protocol: number(2),
+            extra: Map::new(),
+        },
+        players: Players {
+            online: number(4),
+            max: number(5),
+            ..Default::default()
+        },
+        description: Description::Component(json!({ "text": description })),
+        extra: extra
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+        key_order: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MOTD of a vanilla Bedrock Dedicated Server 1.20.40
+    const BDS_MOTD: &str = "MCPE;Dedicated Server;622;1.20.40;2;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;";
+
+    fn pong(motd: &str) -> Vec<u8> {
+        let mut pong = vec![UNCONNECTED_PONG];
+        pong.extend(1_700_000_000_000u64.to_be_bytes());
+        pong.extend(13_253_860_892_328_930_865u64.to_be_bytes());
+        pong.extend(MAGIC);
+        pong.extend((motd.len() as u16).to_be_bytes());
+        pong.extend(motd.as_bytes());
+        pong
+    }
+
+    #[test]
+    fn parses_dedicated_server_pong() {
+        let status = parse_pong(&pong(BDS_MOTD)).unwrap();
+
+        assert_eq!(status.version.name, "1.20.40");
+        assert_eq!(status.version.protocol, 622);
+        assert_eq!(status.players.online, 2);
+        assert_eq!(status.players.max, 10);
+        assert_eq!(
+            status.description.plain(),
+            "Dedicated Server\nBedrock level"
+        );
+        assert_eq!(status.extra["edition"], "MCPE");
+        assert_eq!(status.extra["serverGuid"], "13253860892328930865");
+        assert_eq!(status.extra["levelName"], "Bedrock level");
+        assert_eq!(status.extra["gamemode"], "Survival");
+        assert_eq!(status.extra["gamemodeId"], 1);
+        assert_eq!(status.extra["portV4"], 19132);
+        assert_eq!(status.extra["portV6"], 19133);
+    }
+
+    #[test]
+    fn record_from_pong() {
+        let status =
+            parse_motd("MCPE;Geyser server;686;1.21.2;4;100;1;Geyser;Survival;1;").unwrap();
+        let record = get_full_info("1.2.3.4:19132".parse().unwrap(), status);
+
+        assert_eq!(record.address, "1.2.3.4:19132");
+        assert_eq!(record.edition, "bedrock");
+        assert_eq!(record.motd.as_deref(), Some("Geyser server\nGeyser"));
+        assert_eq!(record.status.players.online, 4);
+        assert_eq!(record.software.unwrap().name, "geyser");
+    }
+
+    #[test]
+    fn older_servers_stop_after_player_counts() {
+        let status = parse_motd("MCPE;\u{a7}aOld server;70;0.14.0;0;20").unwrap();
+
+        assert_eq!(status.description.plain(), "Old server");
+        assert_eq!(status.version.protocol, 70);
+        assert_eq!(status.players.max, 20);
+        assert_eq!(status.extra["levelName"], "");
+        assert_eq!(status.extra["portV4"], -1);
+    }
+
+    #[test]
+    fn odd_numbers_become_unknown() {
+        let status = parse_motd("MCPE;motd;x;1.20;;many;guid").unwrap();
+
+        assert_eq!(status.version.protocol, -1);
+        assert_eq!(status.players.online, -1);
+        assert_eq!(status.players.max, -1);
+    }
+
+    #[test]
+    fn rejects_short_motd() {
+        assert!(parse_motd("").is_err());
+        assert!(parse_motd("MCPE;motd;622;1.20.40;2").is_err());
+        assert!(parse_pong(&pong("MCPE;motd")).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_pongs() {
+        let pong = pong(BDS_MOTD);
+
+        assert!(parse_pong(&[]).is_err());
+        assert!(parse_pong(&pong[..34]).is_err());
+        assert!(parse_pong(&pong[..35]).is_err());
+        assert!(parse_pong(&pong[..pong.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_other_packets() {
+        let mut wrong_id = pong(BDS_MOTD);
+        wrong_id[0] = UNCONNECTED_PING;
+        assert!(parse_pong(&wrong_id).is_err());
+
+        let mut wrong_magic = pong(BDS_MOTD);
+        wrong_magic[20] ^= 0xff;
+        assert!(parse_pong(&wrong_magic).is_err());
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let mut pong = pong("MCPE;caf\u{e9};622;1.20.40;2;10");
+        // Cut the é in half, keeping the length field consistent
+        let at = pong.iter().position(|b| *b == 0xc3).unwrap();
+        pong[at + 1] = b'!';
+
+        let status = parse_pong(&pong).unwrap();
+        assert_eq!(status.description.plain(), "caf\u{fffd}!");
+    }
+}
@@ -0,0 +1,712 @@
+use colored::{ColoredString, Colorize};
+use serde_json::Value;
+
+/// Section sign starting a legacy formatting code
+const SECTION: char = '\u{a7}';
+
+/// https://wiki.vg/Text_formatting
+///
+/// Parsed from any of the JSON forms a server may send: a plain string (possibly with
+/// legacy `§` codes), an object, or an array whose first element is the parent of the rest.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Component {
+    pub content: Content,
+    pub style: Style,
+    pub extra: Vec<Component>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Text(String),
+    Translate { key: String, with: Vec<Component> },
+    Keybind(String),
+    Score { name: String, objective: String },
+    Selector(String),
+}
+
+/// Unset fields are inherited from the parent
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    pub color: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Name, legacy code and RGB of the 16 named colors
+const COLORS: [(&str, char, Color); 16] = [
+    ("black", '0', Color::rgb(0x000000)),
+    ("dark_blue", '1', Color::rgb(0x0000AA)),
+    ("dark_green", '2', Color::rgb(0x00AA00)),
+    ("dark_aqua", '3', Color::rgb(0x00AAAA)),
+    ("dark_red", '4', Color::rgb(0xAA0000)),
+    ("dark_purple", '5', Color::rgb(0xAA00AA)),
+    ("gold", '6', Color::rgb(0xFFAA00)),
+    ("gray", '7', Color::rgb(0xAAAAAA)),
+    ("dark_gray", '8', Color::rgb(0x555555)),
+    ("blue", '9', Color::rgb(0x5555FF)),
+    ("green", 'a', Color::rgb(0x55FF55)),
+    ("aqua", 'b', Color::rgb(0x55FFFF)),
+    ("red", 'c', Color::rgb(0xFF5555)),
+    ("light_purple", 'd', Color::rgb(0xFF55FF)),
+    ("yellow", 'e', Color::rgb(0xFFFF55)),
+    ("white", 'f', Color::rgb(0xFFFFFF)),
+];
+
+/// The few vanilla translations servers commonly send in MOTDs and kick messages.
+/// Unknown keys render as the key followed by their arguments.
+const TRANSLATIONS: [(&str, &str); 13] = [
+    ("chat.type.text", "<%s> %s"),
+    ("chat.type.announcement", "[%s] %s"),
+    ("disconnect.closed", "Connection closed"),
+    ("disconnect.disconnected", "Disconnected by Server"),
+    ("disconnect.loginFailedInfo", "Failed to log in: %s"),
+    (
+        "multiplayer.disconnect.banned",
+        "You are banned from this server",
+    ),
+    (
+        "multiplayer.disconnect.banned.reason",
+        "You are banned from this server.\nReason: %s",
+    ),
+    (
+        "multiplayer.disconnect.not_whitelisted",
+        "You are not white-listed on this server!",
+    ),
+    (
+        "multiplayer.disconnect.incompatible",
+        "Incompatible client! Please use %s",
+    ),
+    (
+        "multiplayer.disconnect.outdated_client",
+        "Incompatible client! Please use %s",
+    ),
+    (
+        "multiplayer.disconnect.outdated_server",
+        "Incompatible client! Please use %s",
+    ),
+    ("multiplayer.disconnect.server_full", "Server is full!"),
+    (
+        "multiplayer.disconnect.unverified_username",
+        "Failed to verify username!",
+    ),
+];
+
+impl Component {
+    pub fn text(text: &str) -> Self {
+        Component {
+            content: Content::Text(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(value: &Value) -> Self {
+        match value {
+            Value::String(text) => Self::parse_legacy(text),
+            Value::Array(parts) => {
+                let mut parts = parts.iter().map(Self::parse);
+                let mut parent = parts.next().unwrap_or_default();
+                parent.extra.extend(parts);
+                parent
+            }
+            Value::Object(object) => {
+                let mut component = match Content::parse(object) {
+                    // Plenty of servers put legacy codes inside JSON text
+                    Content::Text(text) if text.contains(SECTION) => Self::parse_legacy(&text),
+                    content => Component {
+                        content,
+                        ..Default::default()
+                    },
+                };
+                component.style = Style::parse(object);
+
+                if let Some(extra) = object.get("extra") {
+                    match extra {
+                        Value::Array(extra) => {
+                            component.extra.extend(extra.iter().map(Self::parse))
+                        }
+                        extra => component.extra.push(Self::parse(extra)),
+                    }
+                }
+
+                component
+            }
+            Value::Null => Component::default(),
+            other => Self::text(&other.to_string()),
+        }
+    }
+
+    /// Splits `§`-formatted text into children carrying the style in effect for each run.
+    /// A color code resets bold/italic/etc, like the vanilla client does.
+    pub fn parse_legacy(text: &str) -> Self {
+        if !text.contains(SECTION) {
+            return Self::text(text);
+        }
+
+        let mut parent = Component::default();
+        let mut style = Style::default();
+        let mut run = String::new();
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            if c != SECTION {
+                run.push(c);
+                continue;
+            }
+            let Some(code) = chars.next() else {
+                break;
+            };
+
+            if !run.is_empty() {
+                parent.extra.push(Component {
+                    content: Content::Text(std::mem::take(&mut run)),
+                    style,
+                    extra: Vec::new(),
+                });
+            }
+
+            match code.to_ascii_lowercase() {
+                'k' => style.obfuscated = Some(true),
+                'l' => style.bold = Some(true),
+                'm' => style.strikethrough = Some(true),
+                'n' => style.underlined = Some(true),
+                'o' => style.italic = Some(true),
+                'r' => style = Style::reset(),
+                code => {
+                    if let Some(color) = Color::from_code(code) {
+                        style = Style::reset();
+                        style.color = Some(color);
+                    }
+                }
+            }
+        }
+
+        if !run.is_empty() {
+            parent.extra.push(Component {
+                content: Content::Text(run),
+                style,
+                extra: Vec::new(),
+            });
+        }
+
+        parent
+    }
+
+    /// Calls `f` for every piece of text in order, with the resolved style
+    pub fn walk(&self, parent: &Style, f: &mut impl FnMut(&str, &Style)) {
+        let style = self.style.inherit(parent);
+
+        match &self.content {
+            Content::Text(text) => f(text, &style),
+            Content::Translate { key, with } => {
+                let format = TRANSLATIONS
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, format)| *format);
+
+                match format {
+                    Some(format) => {
+                        let mut args = with.iter();
+                        let mut pieces = format.split("%s").peekable();
+                        while let Some(piece) = pieces.next() {
+                            f(piece, &style);
+                            if pieces.peek().is_some() {
+                                if let Some(arg) = args.next() {
+                                    arg.walk(&style, f);
+                                }
+                            }
+                        }
+                    }
+                    None => {
+                        f(key, &style);
+                        for arg in with {
+                            f(" ", &style);
+                            arg.walk(&style, f);
+                        }
+                    }
+                }
+            }
+            Content::Keybind(key) => f(key, &style),
+            Content::Score { name, .. } => f(name, &style),
+            Content::Selector(selector) => f(selector, &style),
+        }
+
+        for child in &self.extra {
+            child.walk(&style, f);
+        }
+    }
+
+    /// Text without formatting, for storage and search
+    pub fn plain(&self) -> String {
+        let mut result = String::new();
+        self.walk(&Style::default(), &mut |text, _| result += text);
+        result
+    }
+
+    /// 24 bit ANSI colors for the console
+    pub fn ansi(&self) -> String {
+        let mut result = String::new();
+        self.walk(&Style::default(), &mut |text, style| {
+            if !text.is_empty() {
+                result += &style.ansi(text).to_string();
+            }
+        });
+        result
+    }
+
+    /// `<span>`s with inline styles, newlines as `<br>`
+    #[allow(dead_code)]
+    pub fn html(&self) -> String {
+        let mut result = String::new();
+        self.walk(&Style::default(), &mut |text, style| {
+            if text.is_empty() {
+                return;
+            }
+
+            let escaped = escape_html(text).replace('\n', "<br>");
+            match style.css() {
+                css if css.is_empty() => result += &escaped,
+                css => result += &format!("<span style=\"{}\">{}</span>", css, escaped),
+            }
+        });
+        result
+    }
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::Text(String::new())
+    }
+}
+
+impl Content {
+    fn parse(object: &serde_json::Map<String, Value>) -> Self {
+        let string = |key: &str| object.get(key).and_then(Value::as_str).map(str::to_string);
+
+        if let Some(key) = string("translate") {
+            let with = object
+                .get("with")
+                .and_then(Value::as_array)
+                .map(|with| with.iter().map(Component::parse).collect())
+                .unwrap_or_default();
+            return Content::Translate { key, with };
+        }
+        if let Some(key) = string("keybind") {
+            return Content::Keybind(key);
+        }
+        if let Some(score) = object.get("score") {
+            return Content::Score {
+                name: score["name"].as_str().unwrap_or_default().to_string(),
+                objective: score["objective"].as_str().unwrap_or_default().to_string(),
+            };
+        }
+        if let Some(selector) = string("selector") {
+            return Content::Selector(selector);
+        }
+
+        match object.get("text") {
+            Some(Value::String(text)) => Content::Text(text.clone()),
+            Some(Value::Null) | None => Content::default(),
+            Some(other) => Content::Text(other.to_string()),
+        }
+    }
+}
+
+impl Style {
+    /// Everything explicitly off, as after `§r`
+    fn reset() -> Self {
+        Style {
+            color: None,
+            bold: Some(false),
+            italic: Some(false),
+            underlined: Some(false),
+            strikethrough: Some(false),
+            obfuscated: Some(false),
+        }
+    }
+
+    fn parse(object: &serde_json::Map<String, Value>) -> Self {
+        // Booleans occasionally arrive as strings
+        let flag = |key: &str| match object.get(key) {
+            Some(Value::Bool(b)) => Some(*b),
+            Some(Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+
+        Style {
+            color: object
+                .get("color")
+                .and_then(Value::as_str)
+                .and_then(Color::parse),
+            bold: flag("bold"),
+            italic: flag("italic"),
+            underlined: flag("underlined"),
+            strikethrough: flag("strikethrough"),
+            obfuscated: flag("obfuscated"),
+        }
+    }
+
+    fn inherit(&self, parent: &Style) -> Style {
+        Style {
+            color: self.color.or(parent.color),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+        }
+    }
+
+    fn ansi(&self, text: &str) -> ColoredString {
+        let mut text = match self.color {
+            Some(Color { r, g, b }) => text.truecolor(r, g, b),
+            None => text.normal(),
+        };
+        if self.bold == Some(true) {
+            text = text.bold();
+        }
+        if self.italic == Some(true) {
+            text = text.italic();
+        }
+        if self.underlined == Some(true) {
+            text = text.underline();
+        }
+        if self.strikethrough == Some(true) {
+            text = text.strikethrough();
+        }
+        if self.obfuscated == Some(true) {
+            text = text.dimmed();
+        }
+
+        text
+    }
+
+    #[allow(dead_code)]
+    fn css(&self) -> String {
+        let mut css = Vec::new();
+        if let Some(color) = self.color {
+            css.push(format!("color:{}", color));
+        }
+        if self.bold == Some(true) {
+            css.push("font-weight:bold".to_string());
+        }
+        if self.italic == Some(true) {
+            css.push("font-style:italic".to_string());
+        }
+        match (
+            self.underlined == Some(true),
+            self.strikethrough == Some(true),
+        ) {
+            (true, true) => css.push("text-decoration:underline line-through".to_string()),
+            (true, false) => css.push("text-decoration:underline".to_string()),
+            (false, true) => css.push("text-decoration:line-through".to_string()),
+            (false, false) => {}
+        }
+
+        css.join(";")
+    }
+}
+
+impl Color {
+    const fn rgb(rgb: u32) -> Self {
+        Color {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        }
+    }
+
+    /// Named color or `#RRGGBB` (1.16+)
+    pub fn parse(color: &str) -> Option<Self> {
+        if let Some(hex) = color.strip_prefix('#') {
+            return u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .map(Color::rgb);
+        }
+
+        COLORS
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(color))
+            .map(|(_, _, color)| *color)
+    }
+
+    fn from_code(code: char) -> Option<Self> {
+        COLORS
+            .iter()
+            .find(|(_, c, _)| *c == code)
+            .map(|(_, _, color)| *color)
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[allow(dead_code)]
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// (text, style) runs as the renderers see them
+    fn runs(component: &Component) -> Vec<(String, Style)> {
+        let mut runs = Vec::new();
+        component.walk(&Style::default(), &mut |text, style| {
+            if !text.is_empty() {
+                runs.push((text.to_string(), *style))
+            }
+        });
+        runs
+    }
+
+    fn color(name: &str) -> Option<Color> {
+        Color::parse(name)
+    }
+
+    #[test]
+    fn plain_string() {
+        let component = Component::parse(&json!("A Minecraft Server"));
+
+        assert_eq!(component, Component::text("A Minecraft Server"));
+        assert_eq!(component.plain(), "A Minecraft Server");
+    }
+
+    #[test]
+    fn array_children_inherit_from_the_first_element() {
+        let component = Component::parse(&json!([
+            {"text": "Hello ", "color": "gold", "bold": true},
+            "world",
+            {"text": "!", "bold": false}
+        ]));
+
+        assert_eq!(component.plain(), "Hello world!");
+        let runs = runs(&component);
+        assert_eq!(runs[1].1.color, color("gold"));
+        assert_eq!(runs[1].1.bold, Some(true));
+        assert_eq!(runs[2].1.color, color("gold"));
+        assert_eq!(runs[2].1.bold, Some(false));
+        assert_eq!(Component::parse(&json!([])), Component::default());
+    }
+
+    #[test]
+    fn extra_inherits_and_overrides_style() {
+        let component = Component::parse(&json!({
+            "text": "",
+            "color": "#1a2b3c",
+            "italic": "true",
+            "extra": [
+                {"text": "a"},
+                {"text": "b", "color": "red", "extra": [{"text": "c", "italic": false}]},
+            ]
+        }));
+
+        assert_eq!(component.plain(), "abc");
+        let runs = runs(&component);
+        assert_eq!(
+            runs[0].1.color,
+            Some(Color {
+                r: 0x1a,
+                g: 0x2b,
+                b: 0x3c
+            })
+        );
+        assert_eq!(runs[0].1.italic, Some(true));
+        assert_eq!(runs[1].1.color, color("red"));
+        assert_eq!(runs[2].1.color, color("red"));
+        assert_eq!(runs[2].1.italic, Some(false));
+    }
+
+    #[test]
+    fn single_extra_object_and_odd_text() {
+        let component = Component::parse(&json!({"text": 1.5, "extra": {"text": "x"}}));
+        assert_eq!(component.plain(), "1.5x");
+
+        assert_eq!(Component::parse(&json!(null)).plain(), "");
+        assert_eq!(Component::parse(&json!(true)).plain(), "true");
+        assert_eq!(Component::parse(&json!({"color": "red"})).plain(), "");
+    }
+
+    #[test]
+    fn translate_fills_arguments() {
+        let component = Component::parse(&json!({
+            "translate": "multiplayer.disconnect.banned.reason",
+            "with": [{"text": "griefing", "color": "red"}]
+        }));
+        assert_eq!(
+            component.plain(),
+            "You are banned from this server.\nReason: griefing"
+        );
+
+        let component = Component::parse(&json!({
+            "translate": "chat.type.text",
+            "with": ["Steve", {"text": "hi"}]
+        }));
+        assert_eq!(component.plain(), "<Steve> hi");
+
+        // Missing arguments leave the gap empty
+        let component =
+            Component::parse(&json!({"translate": "chat.type.text", "with": ["Steve"]}));
+        assert_eq!(component.plain(), "<Steve> ");
+    }
+
+    #[test]
+    fn unknown_translation_shows_key_and_arguments() {
+        let component = Component::parse(&json!({
+            "translate": "custom.motd",
+            "with": ["a", {"text": "b"}]
+        }));
+
+        assert_eq!(component.plain(), "custom.motd a b");
+    }
+
+    #[test]
+    fn other_content_types() {
+        assert_eq!(
+            Component::parse(&json!({"keybind": "key.jump"})).plain(),
+            "key.jump"
+        );
+        assert_eq!(
+            Component::parse(&json!({"score": {"name": "Steve", "objective": "kills"}})).plain(),
+            "Steve"
+        );
+        assert_eq!(Component::parse(&json!({"selector": "@p"})).plain(), "@p");
+    }
+
+    #[test]
+    fn legacy_codes_split_runs() {
+        let component = Component::parse(&json!("\u{a7}aGreen \u{a7}lbold\u{a7}r plain"));
+        let runs = runs(&component);
+
+        assert_eq!(component.plain(), "Green bold plain");
+        assert_eq!(
+            runs[0],
+            (
+                "Green ".to_string(),
+                Style {
+                    color: color("green"),
+                    ..Style::reset()
+                }
+            )
+        );
+        assert_eq!(runs[1].1.color, color("green"));
+        assert_eq!(runs[1].1.bold, Some(true));
+        assert_eq!(runs[2], (" plain".to_string(), Style::reset()));
+    }
+
+    #[test]
+    fn legacy_color_resets_formatting() {
+        let runs = runs(&Component::parse_legacy("\u{a7}l\u{a7}oA\u{a7}CB\u{a7}"));
+
+        assert_eq!(runs[0].1.bold, Some(true));
+        assert_eq!(runs[0].1.italic, Some(true));
+        // Upper case codes work too, and a color drops bold/italic
+        assert_eq!(
+            runs[1],
+            (
+                "B".to_string(),
+                Style {
+                    color: color("red"),
+                    ..Style::reset()
+                }
+            )
+        );
+        assert_eq!(runs.len(), 2);
+    }
+
+    #[test]
+    fn legacy_codes_inside_json_inherit_the_parent() {
+        let component = Component::parse(&json!({
+            "text": "\u{a7}lBold \u{a7}xunknown",
+            "color": "aqua"
+        }));
+        let runs = runs(&component);
+
+        assert_eq!(component.plain(), "Bold unknown");
+        assert_eq!(runs[0].1.color, color("aqua"));
+        assert_eq!(runs[0].1.bold, Some(true));
+        assert_eq!(runs[1].1.color, color("aqua"));
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(color("DARK_RED"), Some(Color::rgb(0xAA0000)));
+        assert_eq!(color("#ffAA00"), Some(Color::rgb(0xFFAA00)));
+        assert_eq!(color("#fff"), None);
+        assert_eq!(color("#gggggg"), None);
+        assert_eq!(color("pink"), None);
+        assert_eq!(Color::rgb(0x0A0B0C).to_string(), "#0a0b0c");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html("<b>\"Tom & Jerry\"</b>"),
+            "&lt;b&gt;&quot;Tom &amp; Jerry&quot;&lt;/b&gt;"
+        );
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+    }
+
+    #[test]
+    fn renders_html() {
+        let component = Component::parse(&json!({
+            "text": "<script>\n",
+            "extra": [{
+                "text": "x",
+                "color": "gold",
+                "bold": true,
+                "italic": true,
+                "underlined": true,
+                "strikethrough": true
+            }]
+        }));
+
+        assert_eq!(
+            component.html(),
+            "&lt;script&gt;<br><span style=\"color:#ffaa00;font-weight:bold;font-style:italic;\
+             text-decoration:underline line-through\">x</span>"
+        );
+    }
+
+    #[test]
+    fn css_for_styles() {
+        assert_eq!(Style::default().css(), "");
+        assert_eq!(Style::reset().css(), "");
+        assert_eq!(
+            Style {
+                underlined: Some(true),
+                ..Style::default()
+            }
+            .css(),
+            "text-decoration:underline"
+        );
+        assert_eq!(
+            Style {
+                strikethrough: Some(true),
+                color: color("black"),
+                ..Style::default()
+            }
+            .css(),
+            "color:#000000;text-decoration:line-through"
+        );
+    }
+}
@@ -1,112 +1,732 @@
 use std::{
-    io::{Error, Result},
-    net::SocketAddr,
+    fmt,
+    io::{Error, ErrorKind, Result},
 };
 
 use minecraft_protocol::{packet_builder::PacketBuilder, types::var_int::VarInt, Packet};
 use mongodb::bson::DateTime;
-use serde_json::{json, Value};
-use tokio::net::TcpSocket;
+use serde_json::{json, Map};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    time::{timeout, Duration},
+};
+
+use crate::{
+    chat::{Component, Content},
+    fingerprint::{classify, ProtocolError, Signals, STATUS_PROTOCOL},
+    forge::parse_mods,
+    model::{AuthMode, Description, Players, PublicKey, ServerRecord, StatusResponse, Version},
+    packets::{
+        packets::{
+            Disconnect, EncryptionRequest, Handshake, LoginPluginRequest, LoginPluginResponse,
+            LoginStart, PacketActions, SetCompression, Status,
+        },
+        registry::{packet_id, packet_kind, Direction, LoginStartLayout, PacketKind, State},
+    },
+    query::get_query,
+    ratelimit::RateLimiter,
+    target::Target,
+    utils::new_socket,
+};
 
-use crate::packets::packets::{Handshake, LoginStart, PacketActions, SetCompression, Status};
+/// Upper bound for one connection: connect, request and reading the answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
 
-pub async fn get_full_info(addr: SocketAddr) -> Result<Value> {
-    let motd = get_status(addr).await?;
-    let licensed = license(addr, motd["version"]["protocol"].as_i64().unwrap_or(765)).await;
+/// Protocol version sent in the MC|PingHost payload (1.6.4)
+const LEGACY_PROTOCOL_VERSION: u8 = 74;
 
-    let license = match licensed {
-        Ok(t) => {
-            if t {
-                1
-            } else {
-                0
-            }
+pub async fn get_full_info(
+    target: &Target,
+    query: bool,
+    limiter: &RateLimiter,
+) -> Result<ServerRecord> {
+    let addr = target.addr;
+
+    // Tokens first, so waiting on a bucket doesn't hold a subnet slot
+    limiter.status.acquire().await;
+    let status = {
+        let _permit = limiter.subnets.acquire(addr.ip()).await;
+        get_status(target).await?
+    };
+
+    limiter.login.acquire().await;
+    let protocol = match status.version.protocol {
+        -1 => STATUS_PROTOCOL,
+        protocol => protocol,
+    };
+    let login = {
+        let _permit = limiter.subnets.acquire(addr.ip()).await;
+        match timeout(PROBE_TIMEOUT, login(target, protocol)).await {
+            Ok(login) => login,
+            Err(elapsed) => Err(elapsed.into()),
         }
-        Err(_) => -1,
     };
+    let protocol_error = login.as_ref().err().and_then(protocol_error);
+    let login = login.ok();
+    let license = login.as_ref().map_or(-1, |login| login.auth.license());
 
-    let mut info = json!({});
-    info["ip"] = json!(addr.ip().to_string());
-    info["port"] = json!(addr.port().to_string());
-    info["license"] = json!(license);
-    info["lastSeen"] = json!(DateTime::now());
+    let (loader, mods) = match parse_mods(&status) {
+        Some((loader, mods)) => (Some(loader), Some(mods)),
+        None => (None, None),
+    };
 
-    if motd["players"]["sample"].is_array() {
-        info["players"] = motd["players"]["sample"].clone();
-    } else {
-        info["players"] = json!([]);
-    }
+    let software = classify(&Signals {
+        status: &status,
+        license: Some(license),
+        auth: login.as_ref().map(|login| &login.auth),
+        channels: login.as_ref().map_or(&[], |login| &login.channels),
+        protocol_error,
+    });
 
-    info["status"] = motd;
+    // Optional enrichment, most servers don't have enable-query set
+    let query = match query {
+        true => {
+            limiter.status.acquire().await;
+            let _permit = limiter.subnets.acquire(addr.ip()).await;
+            get_query(addr).await.ok()
+        }
+        false => None,
+    };
+
+    Ok(ServerRecord {
+        ip: addr.ip().to_string(),
+        port: addr.port(),
+        address: addr.to_string(),
+        hostname: target.hostname.clone(),
+        edition: "java".to_string(),
+        license: Some(license),
+        disconnect_reason: login.as_ref().and_then(|login| login.reason.clone()),
+        login_channels: login
+            .as_ref()
+            .map(|login| login.channels.clone())
+            .filter(|channels| !channels.is_empty()),
+        public_key: login.as_ref().and_then(|login| login.public_key.clone()),
+        public_keys: login
+            .as_ref()
+            .and_then(|login| login.public_key.clone())
+            .map(PublicKey::new)
+            .into_iter()
+            .collect(),
+        auth: login.map(|login| login.auth),
+        first_seen: None,
+        last_seen: Some(DateTime::now()),
+        players: status.players.sample.clone().unwrap_or_default(),
+        motd: Some(status.description.plain()),
+        favicon: None,
+        loader,
+        mods,
+        status,
+        query,
+        software: Some(software),
+    })
+}
 
-    Ok(info)
+/// Modern status ping first, falling back to the pre-1.7 legacy ping. Each
+/// is bounded by `PROBE_TIMEOUT` so tarpits can't hold the subnet permit.
+pub async fn get_status(target: &Target) -> Result<StatusResponse> {
+    match timeout(PROBE_TIMEOUT, get_modern_status(target)).await {
+        Ok(Ok(status)) => Ok(status),
+        _ => timeout(PROBE_TIMEOUT, get_legacy_status(target)).await?,
+    }
 }
 
-pub async fn get_status(addr: SocketAddr) -> Result<Value> {
-    let socket = TcpSocket::new_v4()?;
+pub async fn get_modern_status(target: &Target) -> Result<StatusResponse> {
+    let addr = target.addr;
+    let socket = new_socket(&addr)?;
     let mut stream = socket.connect(addr).await?;
 
     let handshake = Handshake {
-        packet_id: VarInt(0x00),
-        protocol_version: VarInt(765),
-        server_address: addr.ip().to_string(),
+        packet_id: id(PacketKind::Handshake, STATUS_PROTOCOL)?,
+        protocol_version: VarInt(STATUS_PROTOCOL as i32),
+        server_address: target.server_address(),
         server_port: addr.port(),
         next_state: VarInt(0x01),
     };
     handshake.serialize().write(&mut stream).await?;
 
-    let status_req = PacketBuilder::new(VarInt(0x00)).build();
+    let status_req = PacketBuilder::new(id(PacketKind::StatusRequest, STATUS_PROTOCOL)?).build();
     status_req.write(&mut stream).await?;
 
     let response = Packet::read_uncompressed(&mut stream).await?;
     let status = Status::deserialize(&response).await?;
 
-    Ok(serde_json::from_str(&status.status)?)
+    Ok(StatusResponse::from_json(&status.status)?)
+}
+
+/// https://wiki.vg/Server_List_Ping#1.6
+///
+/// 0xFE 0x01 followed by the MC|PingHost plugin message. Pre-1.4 servers ignore
+/// everything after 0xFE / 0x01 and answer in their own format.
+pub async fn get_legacy_status(target: &Target) -> Result<StatusResponse> {
+    let addr = target.addr;
+    let socket = new_socket(&addr)?;
+    let mut stream = socket.connect(addr).await?;
+
+    let host = target.server_address();
+    let channel: Vec<u16> = "MC|PingHost".encode_utf16().collect();
+    let host_utf16: Vec<u16> = host.encode_utf16().collect();
+
+    let mut request = vec![0xFE, 0x01, 0xFA];
+    request.extend((channel.len() as u16).to_be_bytes());
+    channel.iter().for_each(|c| request.extend(c.to_be_bytes()));
+    request.extend((7 + 2 * host_utf16.len() as u16).to_be_bytes());
+    request.push(LEGACY_PROTOCOL_VERSION);
+    request.extend((host_utf16.len() as u16).to_be_bytes());
+    host_utf16
+        .iter()
+        .for_each(|c| request.extend(c.to_be_bytes()));
+    request.extend((addr.port() as i32).to_be_bytes());
+
+    stream.write_all(&request).await?;
+
+    parse_legacy_status(&read_legacy_kick(&mut stream).await?)
+}
+
+/// 0xFF, length in chars, UTF-16BE text
+async fn read_legacy_kick<R: AsyncRead + Unpin>(stream: &mut R) -> Result<String> {
+    if stream.read_u8().await? != 0xFF {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a kick packet"));
+    }
+
+    let len = stream.read_u16().await? as usize;
+    let mut buf = vec![0; len * 2];
+    stream.read_exact(&mut buf).await?;
+
+    let chars: Vec<u16> = buf
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16(&chars).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// 1.4 - 1.6: `§1\0protocol\0version\0motd\0online\0max`
+///
+/// Beta 1.8 - 1.3: `motd§online§max`
+fn parse_legacy_status(response: &str) -> Result<StatusResponse> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "Invalid legacy status");
+
+    let (protocol, version, motd, online, max) =
+        if let Some(body) = response.strip_prefix("\u{a7}1\0") {
+            let fields: Vec<&str> = body.split('\0').collect();
+            if fields.len() != 5 {
+                return Err(invalid());
+            }
+
+            (
+                fields[0].parse::<i64>().map_err(|_| invalid())?,
+                fields[1].to_string(),
+                fields[2].to_string(),
+                fields[3],
+                fields[4],
+            )
+        } else {
+            let mut fields = response.rsplitn(3, '\u{a7}');
+            let max = fields.next().ok_or_else(invalid)?;
+            let online = fields.next().ok_or_else(invalid)?;
+            let motd = fields.next().ok_or_else(invalid)?;
+
+            (
+                -1,
+                "Beta 1.8 - 1.3".to_string(),
+                motd.to_string(),
+                online,
+                max,
+            )
+        };
+
+    Ok(StatusResponse {
+        version: Version {
+            name: version,
+            protocol,
+            extra: Map::new(),
+        },
+        players: Players {
+            online: online.parse().map_err(|_| invalid())?,
+            max: max.parse().map_err(|_| invalid())?,
+            ..Default::default()
+        },
+        description: Description::Component(json!({ "text": motd })),
+        extra: Map::new(),
+        key_order: Vec::new(),
+    })
+}
+
+/// What the server answered to a Login Start, with the kick message if it sent one
+pub struct Login {
+    pub auth: AuthMode,
+    pub reason: Option<String>,
+    /// Channels of the Login Plugin Requests we declined
+    pub channels: Vec<String>,
+    /// Fingerprint from the Encryption Request, see `EncryptionRequest::key_fingerprint`
+    pub public_key: Option<String>,
 }
 
-pub async fn license(addr: SocketAddr, protocol: i64) -> Result<bool> {
-    let socket = TcpSocket::new_v4()?;
+/// Logs in as an offline player until the server either accepts, asks for
+/// encryption or kicks us
+pub async fn login(target: &Target, protocol: i64) -> Result<Login> {
+    let addr = target.addr;
+    let socket = new_socket(&addr)?;
     let mut stream = socket.connect(addr).await?;
 
     let handshake = Handshake {
-        packet_id: VarInt(0x00),
+        packet_id: id(PacketKind::Handshake, protocol)?,
         protocol_version: VarInt(protocol as i32),
-        server_address: addr.ip().to_string(),
+        server_address: target.server_address(),
         server_port: addr.port(),
         next_state: VarInt(0x02),
     };
     handshake.serialize().write(&mut stream).await?;
 
     let login_start = LoginStart {
-        packet_id: VarInt(0x00),
+        packet_id: id(PacketKind::LoginStart, protocol)?,
         name: "NotABot".to_string(),
         uuid: 0,
+        layout: LoginStartLayout::for_protocol(protocol),
     }
     .serialize();
 
     login_start.write(&mut stream).await?;
 
-    let packet = Packet::read_uncompressed(&mut stream).await?;
+    let mut threshold = None;
+    let mut channels = Vec::new();
+    let mut public_key = None;
+    loop {
+        let packet = match Packet::read(&mut stream, threshold).await {
+            Ok(Packet::UnCompressed(packet)) => packet,
+            Ok(Packet::Compressed(packet)) => packet.decompress().await?,
+            // Some servers hang up instead of kicking once we can't answer
+            Err(_) if !channels.is_empty() => {
+                return Ok(Login {
+                    auth: AuthMode::LoginPlugin,
+                    reason: None,
+                    channels,
+                    public_key: None,
+                })
+            }
+            Err(e) => return Err(e),
+        };
 
-    if packet.packet_id.0 == 0x01 {
-        return Ok(true);
-    } else if packet.packet_id.0 == 0x02 {
-        return Ok(false);
-    } else if packet.packet_id.0 == 0x03 {
-        let compression = SetCompression::deserialize(&packet).await?;
+        let kind = packet_kind(
+            State::Login,
+            Direction::Clientbound,
+            protocol,
+            packet.packet_id.0,
+        );
+        let auth = match kind {
+            Some(PacketKind::Disconnect) => {
+                let reason = Disconnect::deserialize(&packet).await?.component();
+                return Ok(Login {
+                    auth: classify_disconnect(&reason),
+                    reason: Some(reason.plain()),
+                    channels,
+                    public_key: None,
+                });
+            }
+            // The packet id alone means online mode. 1.7 and odd servers
+            // send a body we can't parse, those just don't get a key.
+            Some(PacketKind::EncryptionRequest) => {
+                match EncryptionRequest::deserialize(&packet).await {
+                    Ok(request) => {
+                        public_key = Some(request.key_fingerprint());
 
-        let login_success = Packet::read(&mut stream, Some(compression.threshold.0)).await?;
-        if login_success.packet_id().await?.0 == 0x02 {
-            return Ok(false);
-        } else {
-            return Ok(true);
+                        // 1.20.5+ can encrypt without checking the session, any name gets in
+                        match request.should_authenticate {
+                            Some(false) => AuthMode::Offline,
+                            _ => AuthMode::Online,
+                        }
+                    }
+                    Err(_) => AuthMode::Online,
+                }
+            }
+            Some(PacketKind::LoginSuccess) => AuthMode::Offline,
+            Some(PacketKind::SetCompression) => {
+                threshold = Some(SetCompression::deserialize(&packet).await?.threshold.0);
+                continue;
+            }
+            // Velocity forwarding, Forge handshake, ... the vanilla client
+            // answers "not understood" and the server carries on or kicks
+            Some(PacketKind::LoginPluginRequest) => {
+                let request = LoginPluginRequest::deserialize(&packet).await?;
+                channels.push(request.channel);
+
+                let response = LoginPluginResponse {
+                    packet_id: id(PacketKind::LoginPluginResponse, protocol)?,
+                    message_id: request.message_id,
+                    successful: false,
+                    data: Vec::new(),
+                }
+                .serialize();
+                Packet::UnCompressed(response)
+                    .write(&mut stream, threshold)
+                    .await?;
+                continue;
+            }
+            _ => return Err(Error::other(UnexpectedPacket(packet.packet_id.0))),
+        };
+
+        return Ok(Login {
+            auth,
+            reason: None,
+            channels,
+            public_key,
+        });
+    }
+}
+
+/// A packet the login state doesn't have, by id
+#[derive(Debug)]
+struct UnexpectedPacket(i32);
+
+impl fmt::Display for UnexpectedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Packet ID Error: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnexpectedPacket {}
+
+/// Login failures that come from the server speaking the protocol wrong
+fn protocol_error(e: &Error) -> Option<ProtocolError> {
+    if let Some(UnexpectedPacket(id)) = e.get_ref().and_then(|e| e.downcast_ref()) {
+        return Some(ProtocolError::UnexpectedPacket(*id));
+    }
+
+    match e.kind() {
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Some(ProtocolError::Malformed),
+        _ => None,
+    }
+}
+
+/// Packet id from the registry, an error for packets `protocol` doesn't have
+fn id(kind: PacketKind, protocol: i64) -> Result<VarInt> {
+    packet_id(kind, protocol).ok_or_else(|| {
+        Error::new(
+            ErrorKind::Unsupported,
+            format!("{:?} doesn't exist in protocol {}", kind, protocol),
+        )
+    })
+}
+
+/// Vanilla translation keys first, then kick messages from Spigot,
+/// BungeeCord, Velocity and the usual whitelist / ban / anti-bot plugins
+fn classify_disconnect(reason: &Component) -> AuthMode {
+    if let Content::Translate { key, with } = &reason.content {
+        match key.as_str() {
+            "multiplayer.disconnect.not_whitelisted" => return AuthMode::Whitelisted,
+            "multiplayer.disconnect.banned" | "multiplayer.disconnect.banned.reason" => {
+                return AuthMode::Banned
+            }
+            "multiplayer.disconnect.server_full" => return AuthMode::Full,
+            "multiplayer.disconnect.outdated_client"
+            | "multiplayer.disconnect.outdated_server"
+            | "multiplayer.disconnect.incompatible" => {
+                return AuthMode::VersionMismatch {
+                    expected: with.first().map(Component::plain),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let reason = reason.plain().to_lowercase();
+    let has = |patterns: &[&str]| patterns.iter().any(|p| reason.contains(p));
+
+    if has(&[
+        "bungeeguard",
+        "ip forwarding",
+        "connect with velocity",
+        "unable to verify player details",
+        "modern forwarding",
+        "must connect through",
+    ]) {
+        AuthMode::ProxyForwarding
+    } else if has(&["white-list", "whitelist", "white list"]) {
+        AuthMode::Whitelisted
+    } else if has(&["banned", "blacklisted"]) {
+        AuthMode::Banned
+    } else if has(&["server is full", "server full"]) {
+        AuthMode::Full
+    } else if has(&["throttled", "too fast", "wait before reconnecting"]) {
+        AuthMode::Throttled
+    } else if has(&["outdated", "incompatible client", "unsupported client"])
+        // Only with a version: "Please use our website to appeal" is no version hint
+        || (has(&["please use", "compatible with"]) && find_version(&reason).is_some())
+    {
+        AuthMode::VersionMismatch {
+            expected: find_version(&reason),
         }
-    } else if packet.packet_id.0 == 0x00 {
-        return Err(Error::new(std::io::ErrorKind::Other, "Disconnected"));
     } else {
-        return Err(Error::new(
-            std::io::ErrorKind::Other,
-            format!("Packet ID Error: {}", packet.packet_id.0),
-        ));
+        AuthMode::Disconnected
+    }
+}
+
+/// First version-looking word, e.g. `1.20.4` or `1.8.x-1.20.x`
+fn find_version(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_ascii_alphanumeric()))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.'))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Kick packet as a legacy server sends it
+    fn kick(text: &str) -> Vec<u8> {
+        let chars: Vec<u16> = text.encode_utf16().collect();
+        let mut packet = vec![0xFF];
+        packet.extend((chars.len() as u16).to_be_bytes());
+        chars.iter().for_each(|c| packet.extend(c.to_be_bytes()));
+        packet
+    }
+
+    async fn read(bytes: &[u8]) -> Result<StatusResponse> {
+        let mut stream = bytes;
+        parse_legacy_status(&read_legacy_kick(&mut stream).await?)
+    }
+
+    #[tokio::test]
+    async fn reads_1_6_response() {
+        let status = read(&kick(
+            "\u{a7}1\x0078\x001.6.4\x00A Minecraft Server\x003\x0020",
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(status.version.protocol, 78);
+        assert_eq!(status.version.name, "1.6.4");
+        assert_eq!(status.description.plain(), "A Minecraft Server");
+        assert_eq!(status.players.online, 3);
+        assert_eq!(status.players.max, 20);
+        assert!(status.key_order.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_captured_beta_response() {
+        // "Beta§0§20" from a 1.2.5 server
+        let captured = [
+            0xFF, 0x00, 0x09, 0x00, 0x42, 0x00, 0x65, 0x00, 0x74, 0x00, 0x61, 0x00, 0xA7, 0x00,
+            0x30, 0x00, 0xA7, 0x00, 0x32, 0x00, 0x30,
+        ];
+        let status = read(&captured).await.unwrap();
+
+        assert_eq!(status.version.protocol, -1);
+        assert_eq!(status.version.name, "Beta 1.8 - 1.3");
+        assert_eq!(status.description.plain(), "Beta");
+        assert_eq!(status.players.online, 0);
+        assert_eq!(status.players.max, 20);
+    }
+
+    #[test]
+    fn beta_motd_keeps_section_signs() {
+        let status = parse_legacy_status("\u{a7}cRed \u{a7}lserver\u{a7}5\u{a7}10").unwrap();
+
+        assert_eq!(status.description.plain(), "Red server");
+        assert_eq!(status.players.online, 5);
+        assert_eq!(status.players.max, 10);
+    }
+
+    #[test]
+    fn rejects_malformed_legacy_status() {
+        for response in [
+            "",
+            "A Minecraft Server",
+            "motd\u{a7}5",
+            "motd\u{a7}five\u{a7}10",
+            "motd\u{a7}5\u{a7}",
+            "\u{a7}1\x0078\x001.6.4\x00motd\x003",
+            "\u{a7}1\x0078\x001.6.4\x00motd\x003\x0020\x00extra",
+            "\u{a7}1\x00x\x001.6.4\x00motd\x003\x0020",
+            "\u{a7}1\x0078\x001.6.4\x00motd\x003\x00",
+        ] {
+            assert!(parse_legacy_status(response).is_err(), "{:?}", response);
+        }
+    }
+
+    fn kicked(reason: &str) -> AuthMode {
+        classify_disconnect(&Component::text(reason))
+    }
+
+    fn mismatch(expected: Option<&str>) -> AuthMode {
+        AuthMode::VersionMismatch {
+            expected: expected.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn classifies_kick_messages() {
+        let cases = [
+            ("You are not whitelisted on this server!", AuthMode::Whitelisted),
+            ("You are banned from this server!", AuthMode::Banned),
+            ("If you wish to use IP forwarding, please enable it in your BungeeCord config as well!", AuthMode::ProxyForwarding),
+            ("This server requires you to connect with Velocity.", AuthMode::ProxyForwarding),
+            ("Outdated client! Please use 1.20.4", mismatch(Some("1.20.4"))),
+            ("Outdated server! I'm still on 1.8.8", mismatch(Some("1.8.8"))),
+            ("Unsupported client version", mismatch(None)),
+            ("This server is only compatible with 1.8.x-1.20.x", mismatch(Some("1.8.x-1.20.x"))),
+            ("Please use Minecraft 1.12.2 to join", mismatch(Some("1.12.2"))),
+            ("Internal Exception: java.io.IOException", AuthMode::Disconnected),
+            ("", AuthMode::Disconnected),
+        ];
+
+        for (reason, expected) in cases {
+            assert_eq!(kicked(reason), expected, "{}", reason);
+        }
+    }
+
+    #[test]
+    fn appeal_and_queue_hints_are_not_version_mismatches() {
+        let cases = [
+            (
+                "You are banned from this server! Please use the appeal form at example.com",
+                AuthMode::Banned,
+            ),
+            (
+                "You have been blacklisted. Please use our website to appeal",
+                AuthMode::Banned,
+            ),
+            (
+                "Server is full! Please use the queue server",
+                AuthMode::Full,
+            ),
+            (
+                "Please use our new address play.example.com",
+                AuthMode::Disconnected,
+            ),
+            ("Only compatible with our launcher", AuthMode::Disconnected),
+        ];
+
+        for (reason, expected) in cases {
+            assert_eq!(kicked(reason), expected, "{}", reason);
+        }
+    }
+
+    #[test]
+    fn classifies_throttle_and_full() {
+        let cases = [
+            (
+                "Connection throttled! Please wait before reconnecting.",
+                AuthMode::Throttled,
+            ),
+            (
+                "You are logging in too fast, try again later.",
+                AuthMode::Throttled,
+            ),
+            ("The server is full!", AuthMode::Full),
+            ("Server full, try again later", AuthMode::Full),
+        ];
+
+        for (reason, expected) in cases {
+            assert_eq!(kicked(reason), expected, "{}", reason);
+        }
+    }
+
+    #[test]
+    fn classifies_translate_keys() {
+        let translated = |reason: Value| classify_disconnect(&Component::parse(&reason));
+
+        let cases = [
+            (
+                json!({"translate": "multiplayer.disconnect.not_whitelisted"}),
+                AuthMode::Whitelisted,
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.banned"}),
+                AuthMode::Banned,
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.banned.reason", "with": ["Please use 1.20.4"]}),
+                AuthMode::Banned,
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.server_full"}),
+                AuthMode::Full,
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.outdated_client", "with": ["1.20.4"]}),
+                mismatch(Some("1.20.4")),
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.outdated_server", "with": [{"text": "1.8.9"}]}),
+                mismatch(Some("1.8.9")),
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.incompatible", "with": ["1.21.1"]}),
+                mismatch(Some("1.21.1")),
+            ),
+            (
+                json!({"translate": "multiplayer.disconnect.outdated_client"}),
+                mismatch(None),
+            ),
+            // Unknown keys fall back to the text patterns
+            (
+                json!({"translate": "custom.kick", "with": ["whitelist"]}),
+                AuthMode::Whitelisted,
+            ),
+            (
+                json!({"translate": "disconnect.closed"}),
+                AuthMode::Disconnected,
+            ),
+        ];
+
+        for (reason, expected) in cases {
+            assert_eq!(translated(reason.clone()), expected, "{}", reason);
+        }
+    }
+
+    #[test]
+    fn finds_versions() {
+        assert_eq!(
+            find_version("please use 1.20.4!"),
+            Some("1.20.4".to_string())
+        );
+        assert_eq!(
+            find_version("(1.8.x-1.20.x)"),
+            Some("1.8.x-1.20.x".to_string())
+        );
+        assert_eq!(find_version("play.example.com"), None);
+        assert_eq!(find_version("version 2"), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_and_foreign_packets() {
+        let packet = kick("\u{a7}1\x0078\x001.6.4\x00A Minecraft Server\x003\x0020");
+
+        assert!(read(&[]).await.is_err());
+        assert!(read(&packet[..2]).await.is_err());
+        assert!(read(&packet[..packet.len() - 1]).await.is_err());
+
+        // A modern server answering with a status packet
+        let mut modern = packet.clone();
+        modern[0] = 0x00;
+        assert_eq!(
+            read(&modern).await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        // Lone surrogate
+        assert!(read(&[0xFF, 0x00, 0x01, 0xD8, 0x00]).await.is_err());
+    }
+    #[test]
+    fn login_protocol_errors() {
+        let unexpected = Error::other(UnexpectedPacket(0x26));
+        assert_eq!(unexpected.to_string(), "Packet ID Error: 38");
+        assert_eq!(
+            protocol_error(&unexpected),
+            Some(ProtocolError::UnexpectedPacket(0x26))
+        );
+
+        let hung_up = Error::from(ErrorKind::UnexpectedEof);
+        assert_eq!(protocol_error(&hung_up), Some(ProtocolError::Malformed));
+
+        // Says nothing about the server
+        assert_eq!(protocol_error(&Error::from(ErrorKind::TimedOut)), None);
+        assert_eq!(
+            protocol_error(&Error::from(ErrorKind::ConnectionRefused)),
+            None
+        );
     }
 }
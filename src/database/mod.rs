@@ -0,0 +1,207 @@
+use std::{
+    env,
+    io::{Error, Result},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use futures_util::{stream, Stream, TryStreamExt};
+use mongodb::bson::DateTime;
+
+use crate::{
+    favicon::Favicon,
+    model::{ServerRecord, StatusResponse},
+    resolver::Resolved,
+    target::Target,
+};
+
+pub mod mongo;
+pub mod sqlite;
+
+pub use mongo::MongoDBClient;
+pub use sqlite::SqliteStore;
+
+/// Implementations handle their own locking, so one store is shared by every task
+pub type Store = Arc<dyn ServerStore>;
+
+/// Everything the scanner persists. Records are keyed on (ip, port, hostname).
+#[async_trait]
+pub trait ServerStore: Send + Sync {
+    /// Upserts a full record from `get_full_info`, keeping `firstSeen` and merging players
+    async fn add(&self, record: &ServerRecord) -> Result<()>;
+
+    /// Replaces the status of a known record after a re-ping
+    async fn update(&self, target: &Target, status: &StatusResponse) -> Result<()>;
+
+    /// Up to `filter.page_size` keys in storage order, starting after `after` (`None` for
+    /// the first page), and the position to continue from if there may be more.
+    /// Use `get_ips` rather than paging by hand.
+    async fn get_ips_page(
+        &self,
+        filter: &IpFilter,
+        after: Option<String>,
+    ) -> Result<(Vec<Target>, Option<String>)>;
+
+    #[allow(dead_code)]
+    async fn query(&self, query: &ServerQuery) -> Result<Vec<ServerRecord>>;
+
+    /// Icons are stored once and referenced from records by hash, see `Favicon::take`
+    async fn add_favicon(&self, favicon: &Favicon) -> Result<()>;
+
+    /// Hostname -> address mapping, one entry per resolved address
+    async fn add_hostname(&self, resolved: &Resolved) -> Result<()>;
+
+    /// Written on every successful ping so population and software changes can be charted
+    async fn add_snapshot(&self, target: &Target, status: &StatusResponse) -> Result<()>;
+
+    /// Folds raw snapshots older than `SNAPSHOT_RAW_DAYS` into hourly buckets
+    /// (average/peak online, last version) and drops them
+    async fn downsample_snapshots(&self) -> Result<()>;
+
+    /// Current public key fingerprints used by more than one record, with the
+    /// record count, most shared first. Look the servers up with `ServerQuery::public_key`.
+    #[allow(dead_code)]
+    async fn get_shared_public_keys(&self) -> Result<Vec<(String, i64)>>;
+
+    /// Hourly buckets followed by raw snapshots since `since`, oldest first
+    #[allow(dead_code)]
+    async fn get_history(&self, target: &Target, since: DateTime) -> Result<Vec<Snapshot>>;
+
+    /// Points in the history where the version name (and so usually the software) changed
+    #[allow(dead_code)]
+    async fn get_version_changes(
+        &self,
+        target: &Target,
+        since: DateTime,
+    ) -> Result<Vec<(DateTime, String)>> {
+        let mut changes: Vec<(DateTime, String)> = Vec::new();
+
+        for snapshot in self.get_history(target, since).await? {
+            if changes.last().map(|(_, v)| v) != Some(&snapshot.version) {
+                changes.push((snapshot.timestamp, snapshot.version));
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+/// One point of a server's history. Downsampled buckets carry the hourly average in
+/// `online` and the peak in `peak_online`; raw snapshots have both equal.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub timestamp: DateTime,
+    pub online: f64,
+    pub peak_online: i64,
+    pub max: i64,
+    pub version: String,
+    pub protocol: i64,
+    pub description: String,
+}
+
+/// Which records `get_ips` walks
+#[derive(Debug, Clone)]
+pub struct IpFilter {
+    /// Records written before the edition field existed are Java servers
+    pub edition: String,
+    pub seen_within_days: Option<i64>,
+    pub license: Option<i64>,
+    pub page_size: i64,
+}
+
+impl IpFilter {
+    pub fn edition(edition: &str) -> Self {
+        IpFilter {
+            edition: edition.to_string(),
+            seen_within_days: None,
+            license: None,
+            page_size: 1000,
+        }
+    }
+
+    /// Cutoff for `seen_within_days` in milliseconds
+    fn seen_after(&self) -> Option<i64> {
+        self.seen_within_days
+            .map(|days| DateTime::now().timestamp_millis() - days * 24 * HOUR)
+    }
+}
+
+/// Streams the keys matching `filter` a page at a time. The store is only locked
+/// while a page is fetched, so writes from other tasks interleave with a long walk.
+pub fn get_ips(store: Store, filter: IpFilter) -> impl Stream<Item = Result<Target>> {
+    stream::try_unfold(
+        (store, filter, Some(None)),
+        |(store, filter, after)| async move {
+            let Some(after) = after else {
+                return Ok::<_, Error>(None);
+            };
+            let (page, next) = store.get_ips_page(&filter, after).await?;
+
+            Ok(Some((
+                stream::iter(page.into_iter().map(Ok)),
+                (store, filter, next.map(Some)),
+            )))
+        },
+    )
+    .try_flatten()
+}
+
+/// All set fields have to match
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct ServerQuery {
+    pub edition: Option<String>,
+    pub license: Option<i64>,
+    /// Substring of `status.version.name`, case-insensitive
+    pub version: Option<String>,
+    /// Substring of the plain text MOTD, case-insensitive
+    pub motd: Option<String>,
+    /// Favicon hash, to group servers sharing an icon
+    pub favicon: Option<String>,
+    /// Mod id from `mods`
+    pub mod_id: Option<String>,
+    /// `auth.mode`, e.g. `whitelisted`
+    pub auth: Option<String>,
+    /// Any fingerprint in `publicKeys`, to find servers sharing a key
+    pub public_key: Option<String>,
+    /// `software.name`, see `fingerprint`
+    pub software: Option<String>,
+    pub min_online: Option<i64>,
+    pub limit: i64,
+}
+
+/// `STORE=mongodb` (default, see `MongoConfig`) or `STORE=sqlite` with the file at `SQLITE_PATH`
+pub async fn open_store() -> Result<Store> {
+    Ok(
+        match env::var("STORE").unwrap_or("mongodb".to_string()).as_str() {
+            "sqlite" => {
+                let path = env::var("SQLITE_PATH").unwrap_or("data/mse.sqlite".to_string());
+                Arc::new(SqliteStore::open(&path)?)
+            }
+            _ => Arc::new(MongoDBClient::new().await?),
+        },
+    )
+}
+
+fn raw_days() -> i64 {
+    env::var("SNAPSHOT_RAW_DAYS")
+        .unwrap_or("7".to_string())
+        .parse()
+        .unwrap()
+}
+
+fn retention_days() -> i64 {
+    env::var("SNAPSHOT_RETENTION_DAYS")
+        .unwrap_or("365".to_string())
+        .parse()
+        .unwrap()
+}
+
+const HOUR: i64 = 60 * 60 * 1000;
+
+/// Whole hours only, so a bucket is never folded from two partial runs
+fn downsample_cutoff() -> i64 {
+    let cutoff = DateTime::now().timestamp_millis() - raw_days() * 24 * HOUR;
+    cutoff - cutoff % HOUR
+}
@@ -0,0 +1,857 @@
+use std::{
+    env,
+    future::IntoFuture,
+    io::{Error, Result},
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, Bson, DateTime, Document},
+    error::ErrorKind,
+    options::{ClientOptions, IndexOptions, Tls, TlsOptions},
+    Client, Collection, Database, IndexModel,
+};
+use tokio::time::sleep;
+
+use crate::{
+    favicon::Favicon,
+    forge::parse_mods,
+    model::{PublicKey, ServerRecord, StatusResponse},
+    resolver::Resolved,
+    target::Target,
+};
+
+use super::{downsample_cutoff, retention_days, IpFilter, ServerQuery, ServerStore, Snapshot};
+
+/// Connection settings, all from the environment
+pub struct MongoConfig {
+    /// `MONGO_URI`, may carry its own credentials and options
+    pub uri: String,
+    /// `MONGO_DB`
+    pub database: String,
+    /// `MONGO_SERVERS_COLLECTION`, `MONGO_HOSTNAMES_COLLECTION`, `MONGO_SNAPSHOTS_COLLECTION`,
+    /// `MONGO_FAVICONS_COLLECTION`. Hourly buckets go to `<snapshots>_hourly`.
+    pub servers: String,
+    pub hostnames: String,
+    pub snapshots: String,
+    pub favicons: String,
+    /// `MONGO_USERNAME`, `MONGO_PASSWORD`, `MONGO_AUTH_SOURCE`; override the URI
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub auth_source: Option<String>,
+    /// `MONGO_TLS=true`, with optional `MONGO_TLS_CA_FILE`, `MONGO_TLS_CERT_KEY_FILE`
+    /// and `MONGO_TLS_ALLOW_INVALID_CERTIFICATES`
+    pub tls: bool,
+    pub tls_ca_file: Option<PathBuf>,
+    pub tls_cert_key_file: Option<PathBuf>,
+    pub tls_allow_invalid_certificates: bool,
+    /// `MONGO_RETRIES`, attempts per operation before giving up on transient failures
+    pub retries: u32,
+}
+
+impl MongoConfig {
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: &str| env::var(name).unwrap_or(default.to_string());
+        let optional = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+
+        MongoConfig {
+            uri: var("MONGO_URI", "mongodb://mse_mongodb:27017"),
+            database: var("MONGO_DB", "minecraft_search_engine"),
+            servers: var("MONGO_SERVERS_COLLECTION", "servers"),
+            hostnames: var("MONGO_HOSTNAMES_COLLECTION", "hostnames"),
+            snapshots: var("MONGO_SNAPSHOTS_COLLECTION", "snapshots"),
+            favicons: var("MONGO_FAVICONS_COLLECTION", "favicons"),
+            username: optional("MONGO_USERNAME"),
+            password: optional("MONGO_PASSWORD"),
+            auth_source: optional("MONGO_AUTH_SOURCE"),
+            tls: var("MONGO_TLS", "false") == "true",
+            tls_ca_file: optional("MONGO_TLS_CA_FILE").map(PathBuf::from),
+            tls_cert_key_file: optional("MONGO_TLS_CERT_KEY_FILE").map(PathBuf::from),
+            tls_allow_invalid_certificates: var("MONGO_TLS_ALLOW_INVALID_CERTIFICATES", "false")
+                == "true",
+            retries: var("MONGO_RETRIES", "5").parse().unwrap_or(5),
+        }
+    }
+
+    async fn client_options(&self) -> Result<ClientOptions> {
+        let mut options = ClientOptions::parse(&self.uri)
+            .await
+            .map_err(Error::other)?;
+        options.app_name = Some("minecraft_search_engine".to_string());
+
+        if self.username.is_some() || self.password.is_some() || self.auth_source.is_some() {
+            let mut credential = options.credential.take().unwrap_or_default();
+            credential.username = self.username.clone().or(credential.username);
+            credential.password = self.password.clone().or(credential.password);
+            credential.source = self.auth_source.clone().or(credential.source);
+            options.credential = Some(credential);
+        }
+
+        if self.tls {
+            let mut tls = TlsOptions::default();
+            tls.ca_file_path = self.tls_ca_file.clone();
+            tls.cert_key_file_path = self.tls_cert_key_file.clone();
+            tls.allow_invalid_certificates = Some(self.tls_allow_invalid_certificates);
+            options.tls = Some(Tls::Enabled(tls));
+        }
+
+        Ok(options)
+    }
+}
+
+#[allow(dead_code)]
+pub struct MongoDBClient {
+    conn: Client,
+    db: Database,
+    pub servers: Collection<Document>,
+    pub hostnames: Collection<Document>,
+    pub snapshots: Collection<Document>,
+    pub snapshots_hourly: Collection<Document>,
+    /// One document per distinct icon, `_id` is the SHA-256 of the PNG
+    pub favicons: Collection<Document>,
+    retries: u32,
+}
+
+impl MongoDBClient {
+    pub async fn new() -> Result<Self> {
+        Self::with_config(&MongoConfig::from_env()).await
+    }
+
+    /// The driver connects lazily, so the server is pinged here to fail early
+    /// (after retries) instead of on the first write.
+    pub async fn with_config(config: &MongoConfig) -> Result<Self> {
+        let client = Client::with_options(config.client_options().await?).map_err(Error::other)?;
+        let db = client.database(&config.database);
+
+        let client = MongoDBClient {
+            servers: db.collection(&config.servers),
+            hostnames: db.collection(&config.hostnames),
+            snapshots: db.collection(&config.snapshots),
+            snapshots_hourly: db.collection(&format!("{}_hourly", config.snapshots)),
+            favicons: db.collection(&config.favicons),
+            conn: client,
+            db,
+            retries: config.retries.max(1),
+        };
+        client
+            .retry(|| client.db.run_command(doc! {"ping": 1}))
+            .await?;
+        client.migrate_ports().await?;
+        client.backfill_addresses().await?;
+        client.migrate_public_keys().await?;
+        client.dedupe_servers().await?;
+        client.create_indexes().await;
+
+        Ok(client)
+    }
+
+    /// Runs `op` again with exponential backoff while it fails with a network or
+    /// server selection error, up to `MONGO_RETRIES` attempts
+    async fn retry<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: IntoFuture<Output = mongodb::error::Result<T>>,
+    {
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 1;
+
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    eprintln!(
+                        "MongoDB unavailable ({}), retrying in {:?} [{}/{}]",
+                        e, backoff, attempt, self.retries
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(30));
+                    attempt += 1;
+                }
+                Err(e) => return Err(Error::other(e)),
+            }
+        }
+    }
+
+    /// Older versions stored ports as strings. Converted in place before the unique
+    /// index is built; hourly buckets hold the port in `_id`, so those are re-inserted.
+    async fn migrate_ports(&self) -> Result<()> {
+        let to_int = vec![doc! {"$set": {"port": {"$toInt": "$port"}}}];
+        for collection in [&self.servers, &self.hostnames, &self.snapshots] {
+            self.retry(|| {
+                collection.update_many(doc! {"port": {"$type": "string"}}, to_int.clone())
+            })
+            .await?;
+        }
+
+        let legacy = doc! {"_id.port": {"$type": "string"}};
+        let pipeline = vec![
+            doc! {"$match": legacy.clone()},
+            doc! {"$set": {"_id.port": {"$toInt": "$_id.port"}}},
+            doc! {"$merge": {
+                "into": self.snapshots_hourly.name(),
+                "whenMatched": "replace",
+                "whenNotMatched": "insert"
+            }},
+        ];
+        self.retry(|| self.snapshots_hourly.aggregate(pipeline.clone()))
+            .await?;
+        self.retry(|| self.snapshots_hourly.delete_many(legacy.clone()))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Public keys used to be bare fingerprints. They become key documents without
+    /// timestamps, since when they were seen isn't known.
+    async fn migrate_public_keys(&self) -> Result<()> {
+        let to_documents = vec![doc! {"$set": {"publicKeys": {"$map": {
+            "input": "$publicKeys",
+            "as": "key",
+            "in": {"$cond": [
+                {"$eq": [{"$type": "$$key"}, "string"]},
+                {"fingerprint": "$$key"},
+                "$$key"
+            ]}
+        }}}}];
+
+        self.retry(|| {
+            self.servers.update_many(
+                doc! {"publicKeys": {"$type": "string"}},
+                to_documents.clone(),
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records written before `address` existed get `ip:port`, with brackets
+    /// for IPv6 like `SocketAddr` prints it
+    async fn backfill_addresses(&self) -> Result<()> {
+        let port = doc! {"$toString": "$port"};
+        let is_v6 = doc! {"$and": [
+            {"$gte": [{"$indexOfCP": ["$ip", ":"]}, 0]},
+            {"$ne": [{"$substrCP": ["$ip", 0, 1]}, "["]}
+        ]};
+        let set_address = vec![doc! {"$set": {"address": {"$cond": [
+            is_v6,
+            {"$concat": ["[", "$ip", "]:", port.clone()]},
+            {"$concat": ["$ip", ":", port]}
+        ]}}}];
+
+        self.retry(|| {
+            self.servers
+                .update_many(doc! {"address": {"$exists": false}}, set_address.clone())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Databases from before records were upserted hold several documents per
+    /// key, which makes the unique index fail. Keeps the newest one with the
+    /// earliest `firstSeen` and every player. Skipped once the index exists.
+    async fn dedupe_servers(&self) -> Result<()> {
+        // Fails on a fresh database without the collection, nothing to dedupe then
+        let Ok(indexes) = self.retry(|| self.servers.list_index_names()).await else {
+            return Ok(());
+        };
+        if indexes.iter().any(|name| name == "ip_1_port_1_hostname_1") {
+            return Ok(());
+        }
+
+        let pipeline = vec![
+            doc! {"$sort": {"lastSeen": -1, "_id": -1}},
+            doc! {"$group": {
+                // The unique index treats a missing hostname as null
+                "_id": {
+                    "ip": "$ip",
+                    "port": "$port",
+                    "hostname": {"$ifNull": ["$hostname", null]}
+                },
+                "keep": {"$first": "$_id"},
+                "ids": {"$push": "$_id"},
+                "firstSeen": {"$min": "$firstSeen"},
+                "players": {"$push": {"$ifNull": ["$players", []]}},
+                "count": {"$sum": 1}
+            }},
+            doc! {"$match": {"count": {"$gt": 1}}},
+            doc! {"$project": {
+                "keep": 1,
+                "ids": 1,
+                "firstSeen": 1,
+                "players": {"$reduce": {
+                    "input": "$players",
+                    "initialValue": [],
+                    "in": {"$setUnion": ["$$value", "$$this"]}
+                }}
+            }},
+        ];
+
+        let mut cursor = self
+            .retry(|| {
+                self.servers
+                    .aggregate(pipeline.clone())
+                    .allow_disk_use(true)
+            })
+            .await?;
+
+        let mut removed = 0;
+        while cursor.advance().await.map_err(Error::other)? {
+            let group = cursor.deserialize_current().map_err(Error::other)?;
+            let keep = group.get_object_id("keep").map_err(Error::other)?;
+            let duplicates: Vec<Bson> = group
+                .get_array("ids")
+                .map_err(Error::other)?
+                .iter()
+                .filter(|id| id.as_object_id() != Some(keep))
+                .cloned()
+                .collect();
+
+            let mut set =
+                doc! {"players": group.get("players").cloned().unwrap_or(Bson::Array(vec![]))};
+            if let Ok(first_seen) = group.get_datetime("firstSeen") {
+                set.insert("firstSeen", *first_seen);
+            }
+
+            self.retry(|| {
+                self.servers
+                    .update_one(doc! {"_id": keep}, doc! {"$set": set.clone()})
+            })
+            .await?;
+            self.retry(|| {
+                self.servers
+                    .delete_many(doc! {"_id": {"$in": duplicates.clone()}})
+            })
+            .await?;
+            removed += duplicates.len();
+        }
+
+        if removed > 0 {
+            println!("Removed {} duplicate server records", removed);
+        }
+
+        Ok(())
+    }
+
+    /// Idempotent. Servers are unique on (ip, port, hostname): a hostname
+    /// record shares its address with the raw IP record and other virtual hosts.
+    async fn create_indexes(&self) {
+        let unique = IndexOptions::builder().unique(true).build();
+
+        let servers = [
+            IndexModel::builder()
+                .keys(doc! {"ip": 1, "port": 1, "hostname": 1})
+                .options(unique.clone())
+                .build(),
+            IndexModel::builder().keys(doc! {"lastSeen": -1}).build(),
+            IndexModel::builder().keys(doc! {"edition": 1}).build(),
+            IndexModel::builder().keys(doc! {"license": 1}).build(),
+            IndexModel::builder().keys(doc! {"mods.id": 1}).build(),
+            IndexModel::builder().keys(doc! {"favicon": 1}).build(),
+            IndexModel::builder()
+                .keys(doc! {"software.name": 1})
+                .build(),
+            IndexModel::builder().keys(doc! {"auth.mode": 1}).build(),
+            IndexModel::builder()
+                .keys(doc! {"publicKeys.fingerprint": 1})
+                .build(),
+        ];
+        let hostnames = IndexModel::builder()
+            .keys(doc! {"hostname": 1, "ip": 1, "port": 1})
+            .options(unique)
+            .build();
+
+        for index in servers {
+            if let Err(e) = self.servers.create_index(index).await {
+                eprintln!("Failed to create index on servers: {}", e);
+            }
+        }
+        if let Err(e) = self.hostnames.create_index(hostnames).await {
+            eprintln!("Failed to create index on hostnames: {}", e);
+        }
+
+        let snapshots = IndexModel::builder()
+            .keys(doc! {"ip": 1, "port": 1, "hostname": 1, "timestamp": -1})
+            .build();
+        if let Err(e) = self.snapshots.create_index(snapshots).await {
+            eprintln!("Failed to create index on snapshots: {}", e);
+        }
+
+        let hourly = [
+            IndexModel::builder()
+                .keys(doc! {"_id.ip": 1, "_id.port": 1, "_id.hostname": 1, "_id.hour": -1})
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"_id.hour": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Duration::from_secs(retention_days() as u64 * 24 * 60 * 60))
+                        .build(),
+                )
+                .build(),
+        ];
+        for index in hourly {
+            if let Err(e) = self.snapshots_hourly.create_index(index).await {
+                eprintln!("Failed to create index on snapshots_hourly: {}", e);
+            }
+        }
+    }
+    /// Moves `lastSeen` of a known fingerprint forward, or appends the key.
+    /// Keys seen again keep their `firstSeen`, which `$addToSet` can't do.
+    async fn add_public_key(&self, server: &Document, key: &PublicKey) -> Result<()> {
+        let mut known = server.clone();
+        known.insert("publicKeys.fingerprint", &key.fingerprint);
+        let last_seen = key.last_seen.unwrap_or_else(DateTime::now);
+
+        let result = self
+            .retry(|| {
+                self.servers.update_one(
+                    known.clone(),
+                    doc! {"$set": {"publicKeys.$.lastSeen": last_seen}},
+                )
+            })
+            .await?;
+        if result.matched_count > 0 {
+            return Ok(());
+        }
+
+        let key = mongodb::bson::to_bson(key).map_err(Error::other)?;
+        self.retry(|| {
+            self.servers
+                .update_one(server.clone(), doc! {"$push": {"publicKeys": key.clone()}})
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ServerStore for MongoDBClient {
+    /// Raw IP records have no hostname; forced-host proxies answer differently per
+    /// hostname, so those get a record each.
+    async fn add(&self, record: &ServerRecord) -> Result<()> {
+        let mut record = record.clone();
+        if let Some(favicon) = Favicon::take(&mut record.status) {
+            self.add_favicon(&favicon).await?;
+            record.favicon = Some(favicon.hash);
+        }
+
+        let mut set = mongodb::bson::to_document(&record).map_err(Error::other)?;
+        let players = set.remove("players").unwrap_or(Bson::Array(vec![]));
+        set.remove("publicKeys");
+        set.remove("firstSeen");
+        set.insert("lastSeen", DateTime::now());
+
+        let filter = doc! {
+            "ip": &record.ip,
+            "port": i32::from(record.port),
+            "hostname": &record.hostname
+        };
+
+        let update = doc! {
+            "$set": set,
+            "$setOnInsert": {
+                "firstSeen": DateTime::now()
+            },
+            "$addToSet": {
+                "players": {
+                    "$each": players
+                }
+            }
+        };
+
+        self.retry(|| {
+            self.servers
+                .update_one(filter.clone(), update.clone())
+                .upsert(true)
+        })
+        .await?;
+
+        for key in &record.public_keys {
+            self.add_public_key(&filter, key).await?;
+        }
+        Ok(())
+    }
+
+    async fn update(&self, target: &Target, status: &StatusResponse) -> Result<()> {
+        let mut status = status.clone();
+        let favicon = Favicon::take(&mut status);
+        if let Some(favicon) = &favicon {
+            self.add_favicon(favicon).await?;
+        }
+        let status = &status;
+
+        let mut set = doc! {
+            "status": mongodb::bson::to_bson(status).map_err(Error::other)?,
+            "motd": status.description.plain(),
+            "favicon": favicon.map(|favicon| favicon.hash),
+            "lastSeen": DateTime::now()
+        };
+        if let Some((loader, mods)) = parse_mods(status) {
+            set.insert("loader", loader);
+            set.insert("mods", mongodb::bson::to_bson(&mods).map_err(Error::other)?);
+        }
+
+        let players = status.players.sample.clone().unwrap_or_default();
+
+        self.servers
+            .update_one(
+                doc! {
+                    "ip": target.addr.ip().to_string(),
+                    "port": i32::from(target.addr.port()),
+                    "hostname": &target.hostname
+                },
+                doc! {
+                    "$set": set,
+                    "$addToSet": {
+                        "players": {
+                            "$each": mongodb::bson::to_bson(&players).map_err(Error::other)?
+                        }
+                    }
+                },
+            )
+            .await
+            .map_err(Error::other)?;
+
+        Ok(())
+    }
+
+    async fn get_ips_page(
+        &self,
+        filter: &IpFilter,
+        after: Option<String>,
+    ) -> Result<(Vec<Target>, Option<String>)> {
+        let mut query = match filter.edition.as_str() {
+            "java" => doc! {"edition": {"$ne": "bedrock"}},
+            edition => doc! {"edition": edition},
+        };
+        if let Some(seen_after) = filter.seen_after() {
+            query.insert("lastSeen", doc! {"$gte": DateTime::from_millis(seen_after)});
+        }
+        if let Some(license) = filter.license {
+            query.insert("license", license);
+        }
+        if let Some(after) = after {
+            query.insert(
+                "_id",
+                doc! {"$gt": ObjectId::parse_str(after).map_err(Error::other)?},
+            );
+        }
+
+        let mut cursor = self
+            .retry(|| {
+                self.servers
+                    .find(query.clone())
+                    .projection(doc! {"ip": 1, "port": 1, "hostname": 1})
+                    .sort(doc! {"_id": 1})
+                    .limit(filter.page_size)
+            })
+            .await?;
+
+        let mut results = Vec::new();
+        let mut last = None;
+        let mut fetched = 0;
+        while cursor.advance().await.map_err(Error::other)? {
+            let server = cursor.current();
+            last = Some(server.get_object_id("_id").map_err(Error::other)?);
+            fetched += 1;
+
+            let ip = server
+                .get_str("ip")
+                .unwrap_or("localhost")
+                .trim_matches(['[', ']'])
+                .parse();
+            let port = server
+                .get_i32("port")
+                .ok()
+                .and_then(|p| u16::try_from(p).ok());
+            let hostname = server.get_str("hostname").ok().map(|h| h.to_string());
+
+            if let (Ok(ip), Some(port)) = (ip, port) {
+                results.push(Target::new(SocketAddr::new(ip, port), hostname));
+            }
+        }
+
+        // A short page is the last one
+        let next = match fetched >= filter.page_size {
+            true => last.map(|id| id.to_hex()),
+            false => None,
+        };
+
+        Ok((results, next))
+    }
+
+    async fn query(&self, query: &ServerQuery) -> Result<Vec<ServerRecord>> {
+        let mut filter = doc! {};
+        match query.edition.as_deref() {
+            Some("java") => filter.insert("edition", doc! {"$ne": "bedrock"}),
+            Some(edition) => filter.insert("edition", edition),
+            None => None,
+        };
+        if let Some(license) = query.license {
+            filter.insert("license", license);
+        }
+        if let Some(version) = &query.version {
+            filter.insert(
+                "status.version.name",
+                doc! {"$regex": escape_regex(version), "$options": "i"},
+            );
+        }
+        if let Some(motd) = &query.motd {
+            filter.insert("motd", doc! {"$regex": escape_regex(motd), "$options": "i"});
+        }
+        if let Some(mod_id) = &query.mod_id {
+            filter.insert("mods.id", mod_id);
+        }
+        if let Some(favicon) = &query.favicon {
+            filter.insert("favicon", favicon);
+        }
+        if let Some(auth) = &query.auth {
+            filter.insert("auth.mode", auth);
+        }
+        if let Some(public_key) = &query.public_key {
+            filter.insert("publicKeys.fingerprint", public_key);
+        }
+        if let Some(software) = &query.software {
+            filter.insert("software.name", software);
+        }
+        if let Some(min_online) = query.min_online {
+            filter.insert("status.players.online", doc! {"$gte": min_online});
+        }
+
+        let mut cursor = self
+            .servers
+            .clone_with_type::<ServerRecord>()
+            .find(filter)
+            .sort(doc! {"lastSeen": -1})
+            .limit(query.limit)
+            .await
+            .map_err(Error::other)?;
+
+        let mut results = Vec::new();
+        while cursor.advance().await.map_err(Error::other)? {
+            results.push(cursor.deserialize_current().map_err(Error::other)?);
+        }
+
+        Ok(results)
+    }
+
+    async fn add_favicon(&self, favicon: &Favicon) -> Result<()> {
+        let update = doc! {
+            "$setOnInsert": {
+                "png": Binary {
+                    subtype: BinarySubtype::Generic,
+                    bytes: favicon.png.clone()
+                },
+                "width": favicon.width,
+                "height": favicon.height,
+                "firstSeen": DateTime::now()
+            },
+            "$set": {
+                "lastSeen": DateTime::now()
+            }
+        };
+
+        self.retry(|| {
+            self.favicons
+                .update_one(doc! {"_id": &favicon.hash}, update.clone())
+                .upsert(true)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_hostname(&self, resolved: &Resolved) -> Result<()> {
+        let target = &resolved.target;
+
+        self.hostnames
+            .update_one(
+                doc! {
+                    "hostname": &target.hostname,
+                    "ip": target.addr.ip().to_string(),
+                    "port": i32::from(target.addr.port())
+                },
+                doc! {
+                    "$set": {
+                        "srv": resolved.srv,
+                        "lastResolved": DateTime::now()
+                    }
+                },
+            )
+            .upsert(true)
+            .await
+            .map_err(Error::other)?;
+
+        Ok(())
+    }
+
+    async fn add_snapshot(&self, target: &Target, status: &StatusResponse) -> Result<()> {
+        let snapshot = doc! {
+            "ip": target.addr.ip().to_string(),
+            "port": i32::from(target.addr.port()),
+            "hostname": &target.hostname,
+            "timestamp": DateTime::now(),
+            "online": status.players.online,
+            "max": status.players.max,
+            "version": &status.version.name,
+            "protocol": status.version.protocol,
+            "description": status.description.plain()
+        };
+
+        self.snapshots
+            .insert_one(snapshot)
+            .await
+            .map_err(Error::other)?;
+        Ok(())
+    }
+
+    /// Hourly buckets expire after `SNAPSHOT_RETENTION_DAYS` through the TTL index
+    async fn downsample_snapshots(&self) -> Result<()> {
+        let cutoff = DateTime::from_millis(downsample_cutoff());
+
+        let pipeline = vec![
+            doc! {"$match": {"timestamp": {"$lt": cutoff}}},
+            doc! {"$sort": {"timestamp": 1}},
+            doc! {"$group": {
+                "_id": {
+                    "ip": "$ip",
+                    "port": "$port",
+                    "hostname": "$hostname",
+                    "hour": {"$dateTrunc": {"date": "$timestamp", "unit": "hour"}}
+                },
+                "avgOnline": {"$avg": "$online"},
+                "peakOnline": {"$max": "$online"},
+                "max": {"$last": "$max"},
+                "version": {"$last": "$version"},
+                "protocol": {"$last": "$protocol"},
+                "description": {"$last": "$description"},
+                "samples": {"$sum": 1}
+            }},
+            doc! {"$merge": {
+                "into": self.snapshots_hourly.name(),
+                "whenMatched": "replace",
+                "whenNotMatched": "insert"
+            }},
+        ];
+
+        self.snapshots
+            .aggregate(pipeline)
+            .await
+            .map_err(Error::other)?;
+        self.snapshots
+            .delete_many(doc! {"timestamp": {"$lt": cutoff}})
+            .await
+            .map_err(Error::other)?;
+
+        Ok(())
+    }
+
+    async fn get_shared_public_keys(&self) -> Result<Vec<(String, i64)>> {
+        let pipeline = vec![
+            doc! {"$match": {"publicKey": {"$type": "string"}}},
+            doc! {"$group": {"_id": "$publicKey", "count": {"$sum": 1}}},
+            doc! {"$match": {"count": {"$gt": 1}}},
+            doc! {"$sort": {"count": -1}},
+        ];
+
+        let mut cursor = self
+            .servers
+            .aggregate(pipeline)
+            .await
+            .map_err(Error::other)?;
+
+        let mut keys = Vec::new();
+        while cursor.advance().await.map_err(Error::other)? {
+            let group = cursor.deserialize_current().map_err(Error::other)?;
+            if let Ok(key) = group.get_str("_id") {
+                keys.push((key.to_string(), group.get_i32("count").unwrap_or(0) as i64));
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn get_history(&self, target: &Target, since: DateTime) -> Result<Vec<Snapshot>> {
+        let mut history = Vec::new();
+
+        let mut hourly = self
+            .snapshots_hourly
+            .find(doc! {
+                "_id.ip": target.addr.ip().to_string(),
+                "_id.port": i32::from(target.addr.port()),
+                "_id.hostname": &target.hostname,
+                "_id.hour": {"$gte": since}
+            })
+            .sort(doc! {"_id.hour": 1})
+            .await
+            .map_err(Error::other)?;
+        while hourly.advance().await.map_err(Error::other)? {
+            let bucket = hourly.deserialize_current().map_err(Error::other)?;
+            let id = bucket.get_document("_id").map_err(Error::other)?;
+
+            history.push(Snapshot {
+                timestamp: *id.get_datetime("hour").map_err(Error::other)?,
+                online: bucket.get_f64("avgOnline").unwrap_or(-1.0),
+                peak_online: bucket.get_i64("peakOnline").unwrap_or(-1),
+                max: bucket.get_i64("max").unwrap_or(-1),
+                version: bucket.get_str("version").unwrap_or("").to_string(),
+                protocol: bucket.get_i64("protocol").unwrap_or(-1),
+                description: bucket.get_str("description").unwrap_or("").to_string(),
+            });
+        }
+
+        let mut raw = self
+            .snapshots
+            .find(doc! {
+                "ip": target.addr.ip().to_string(),
+                "port": i32::from(target.addr.port()),
+                "hostname": &target.hostname,
+                "timestamp": {"$gte": since}
+            })
+            .sort(doc! {"timestamp": 1})
+            .await
+            .map_err(Error::other)?;
+        while raw.advance().await.map_err(Error::other)? {
+            let snapshot = raw.deserialize_current().map_err(Error::other)?;
+            let online = snapshot.get_i64("online").unwrap_or(-1);
+
+            history.push(Snapshot {
+                timestamp: *snapshot.get_datetime("timestamp").map_err(Error::other)?,
+                online: online as f64,
+                peak_online: online,
+                max: snapshot.get_i64("max").unwrap_or(-1),
+                version: snapshot.get_str("version").unwrap_or("").to_string(),
+                protocol: snapshot.get_i64("protocol").unwrap_or(-1),
+                description: snapshot.get_str("description").unwrap_or("").to_string(),
+            });
+        }
+
+        Ok(history)
+    }
+}
+
+fn escape_regex(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match c.is_ascii_alphanumeric() || c == ' ' {
+            true => vec![c],
+            false => vec!['\\', c],
+        })
+        .collect()
+}
+
+/// Failures worth waiting out: the server is unreachable or mid-failover
+fn is_transient(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        ErrorKind::Io(_)
+            | ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. }
+    ) || e.contains_label("RetryableWriteError")
+}
@@ -0,0 +1,1068 @@
+use std::{
+    fs,
+    io::{Error, Result},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use mongodb::bson::DateTime;
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    favicon::Favicon,
+    forge::parse_mods,
+    model::{PublicKey, ServerRecord, StatusResponse},
+    resolver::Resolved,
+    target::Target,
+};
+
+use super::{
+    downsample_cutoff, retention_days, IpFilter, ServerQuery, ServerStore, Snapshot, HOUR,
+};
+
+/// Single-file store for running without MongoDB. Records are kept as JSON documents
+/// shaped like the Mongo ones, with the key and filter fields mirrored into columns.
+/// Raw IP records use an empty hostname, since NULL never conflicts in a primary key.
+///
+/// rusqlite blocks, so every statement runs on tokio's blocking pool, see `blocking`.
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path).map_err(Error::other)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS servers (
+                ip TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                hostname TEXT NOT NULL DEFAULT '',
+                edition TEXT,
+                license INTEGER,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                document TEXT NOT NULL,
+                PRIMARY KEY (ip, port, hostname)
+            );
+            CREATE INDEX IF NOT EXISTS servers_last_seen ON servers (last_seen DESC);
+            CREATE INDEX IF NOT EXISTS servers_edition ON servers (edition);
+            CREATE INDEX IF NOT EXISTS servers_license ON servers (license);
+            CREATE TABLE IF NOT EXISTS hostnames (
+                hostname TEXT NOT NULL,
+                ip TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                srv INTEGER NOT NULL,
+                last_resolved INTEGER NOT NULL,
+                PRIMARY KEY (hostname, ip, port)
+            );
+            CREATE TABLE IF NOT EXISTS snapshots (
+                ip TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                hostname TEXT NOT NULL DEFAULT '',
+                timestamp INTEGER NOT NULL,
+                online INTEGER NOT NULL,
+                max INTEGER NOT NULL,
+                version TEXT NOT NULL,
+                protocol INTEGER NOT NULL,
+                description TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS snapshots_target
+                ON snapshots (ip, port, hostname, timestamp DESC);
+            CREATE TABLE IF NOT EXISTS snapshots_hourly (
+                ip TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                hostname TEXT NOT NULL DEFAULT '',
+                hour INTEGER NOT NULL,
+                avg_online REAL NOT NULL,
+                peak_online INTEGER NOT NULL,
+                max INTEGER NOT NULL,
+                version TEXT NOT NULL,
+                protocol INTEGER NOT NULL,
+                description TEXT NOT NULL,
+                samples INTEGER NOT NULL,
+                PRIMARY KEY (ip, port, hostname, hour)
+            );
+            CREATE INDEX IF NOT EXISTS snapshots_hourly_hour ON snapshots_hourly (hour);
+            CREATE TABLE IF NOT EXISTS favicons (
+                hash TEXT PRIMARY KEY,
+                png BLOB NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL
+            );",
+        )
+        .map_err(Error::other)?;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` with the connection on a blocking thread, so disk I/O and waiting for
+    /// the lock never stall the runtime's workers
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap()))
+            .await
+            .map_err(Error::other)?
+    }
+}
+
+/// (ip, port, hostname) with an empty hostname for raw IP records
+type Key = (String, u16, String);
+
+fn key(target: &Target) -> Key {
+    (
+        target.addr.ip().to_string(),
+        target.addr.port(),
+        target.hostname.clone().unwrap_or_default(),
+    )
+}
+
+/// Merges `update` into the stored document the way `$set` + `$addToSet` does,
+/// `add_to_set` being (array field, new elements). Keys merge like in the Mongo store.
+fn upsert(
+    conn: &Connection,
+    key: &Key,
+    update: &Value,
+    add_to_set: &[(&str, Vec<Value>)],
+    public_keys: &[PublicKey],
+) -> Result<()> {
+    let now = DateTime::now().timestamp_millis();
+
+    let existing: Option<(String, i64)> = conn
+        .query_row(
+            "SELECT document, first_seen FROM servers WHERE ip = ?1 AND port = ?2 AND hostname = ?3",
+            params![key.0, key.1, key.2],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(Error::other)?;
+
+    let (mut document, first_seen) = match existing {
+        Some((document, first_seen)) => (serde_json::from_str(&document)?, first_seen),
+        None => (Value::Object(Default::default()), now),
+    };
+
+    if let (Value::Object(document), Value::Object(update)) = (&mut document, update) {
+        for (k, v) in update {
+            document.insert(k.clone(), v.clone());
+        }
+    }
+    for (field, values) in add_to_set {
+        let mut merged = document[*field].as_array().cloned().unwrap_or_default();
+        for value in values {
+            if !merged.contains(value) {
+                merged.push(value.clone());
+            }
+        }
+        // Same shape as a record read back from Mongo
+        document[*field] = Value::Array(merged);
+    }
+    merge_public_keys(&mut document, public_keys)?;
+    document["firstSeen"] = serde_json::to_value(DateTime::from_millis(first_seen))?;
+    document["lastSeen"] = serde_json::to_value(DateTime::from_millis(now))?;
+
+    conn.execute(
+        "INSERT INTO servers (ip, port, hostname, edition, license, first_seen, last_seen, document)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        ON CONFLICT (ip, port, hostname) DO UPDATE SET
+            edition = excluded.edition,
+            license = excluded.license,
+            last_seen = excluded.last_seen,
+            document = excluded.document",
+        params![
+            key.0,
+            key.1,
+            key.2,
+            document["edition"].as_str(),
+            document["license"].as_i64(),
+            first_seen,
+            now,
+            document.to_string()
+        ],
+    )
+    .map_err(Error::other)?;
+
+    Ok(())
+}
+
+/// Known fingerprints get the new `lastSeen`, new ones are appended
+fn merge_public_keys(document: &mut Value, keys: &[PublicKey]) -> Result<()> {
+    let mut stored: Vec<PublicKey> =
+        serde_json::from_value(document["publicKeys"].take()).unwrap_or_default();
+
+    for key in keys {
+        match stored.iter_mut().find(|k| k.fingerprint == key.fingerprint) {
+            Some(existing) => existing.last_seen = key.last_seen.or(existing.last_seen),
+            None => stored.push(key.clone()),
+        }
+    }
+    document["publicKeys"] = serde_json::to_value(stored)?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl ServerStore for SqliteStore {
+    async fn add(&self, record: &ServerRecord) -> Result<()> {
+        let mut record = record.clone();
+        if let Some(favicon) = Favicon::take(&mut record.status) {
+            self.add_favicon(&favicon).await?;
+            record.favicon = Some(favicon.hash);
+        }
+
+        let mut update = serde_json::to_value(&record)?;
+        if let Some(fields) = update.as_object_mut() {
+            fields.remove("firstSeen");
+            fields.remove("lastSeen");
+            fields.remove("players");
+            fields.remove("publicKeys");
+        }
+
+        let key = (
+            record.ip.clone(),
+            record.port,
+            record.hostname.clone().unwrap_or_default(),
+        );
+        let add_to_set = [("players", to_values(&record.players)?)];
+        let public_keys = record.public_keys;
+        self.blocking(move |conn| upsert(conn, &key, &update, &add_to_set, &public_keys))
+            .await
+    }
+
+    async fn update(&self, target: &Target, status: &StatusResponse) -> Result<()> {
+        let mut status = status.clone();
+        let favicon = Favicon::take(&mut status);
+        if let Some(favicon) = &favicon {
+            self.add_favicon(favicon).await?;
+        }
+        let status = &status;
+
+        let mut update = json!({
+            "status": status,
+            "motd": status.description.plain(),
+            "favicon": favicon.map(|favicon| favicon.hash)
+        });
+        if let Some((loader, mods)) = parse_mods(status) {
+            update["loader"] = json!(loader);
+            update["mods"] = json!(mods);
+        }
+        let add_to_set = [(
+            "players",
+            to_values(&status.players.sample.clone().unwrap_or_default())?,
+        )];
+        let key = key(target);
+
+        self.blocking(move |conn| {
+            // Like the Mongo update, re-pings never create records
+            let exists = conn
+                .query_row(
+                    "SELECT 1 FROM servers WHERE ip = ?1 AND port = ?2 AND hostname = ?3",
+                    params![key.0, key.1, key.2],
+                    |_| Ok(()),
+                )
+                .optional()
+                .map_err(Error::other)?;
+            if exists.is_none() {
+                return Ok(());
+            }
+
+            upsert(conn, &key, &update, &add_to_set, &[])
+        })
+        .await
+    }
+
+    async fn get_ips_page(
+        &self,
+        filter: &IpFilter,
+        after: Option<String>,
+    ) -> Result<(Vec<Target>, Option<String>)> {
+        let mut sql = "SELECT rowid, ip, port, hostname FROM servers WHERE rowid > ?".to_string();
+        let after: i64 = after
+            .map(|a| a.parse())
+            .transpose()
+            .map_err(Error::other)?
+            .unwrap_or(0);
+        let mut args: Vec<SqlValue> = vec![after.into()];
+
+        match filter.edition.as_str() {
+            "java" => sql.push_str(" AND edition IS NOT 'bedrock'"),
+            edition => {
+                sql.push_str(" AND edition = ?");
+                args.push(edition.to_string().into());
+            }
+        }
+        if let Some(seen_after) = filter.seen_after() {
+            sql.push_str(" AND last_seen >= ?");
+            args.push(seen_after.into());
+        }
+        if let Some(license) = filter.license {
+            sql.push_str(" AND license = ?");
+            args.push(license.into());
+        }
+        sql.push_str(" ORDER BY rowid LIMIT ?");
+        args.push(filter.page_size.into());
+
+        let rows: Vec<(i64, String, u16, String)> = self
+            .blocking(move |conn| {
+                let mut statement = conn.prepare(&sql).map_err(Error::other)?;
+                let rows = statement
+                    .query_map(params_from_iter(args), |row| {
+                        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                    })
+                    .map_err(Error::other)?;
+
+                rows.collect::<rusqlite::Result<_>>().map_err(Error::other)
+            })
+            .await?;
+
+        let mut results = Vec::new();
+        let mut last = None;
+        let mut fetched = 0;
+        for (rowid, ip, port, hostname) in rows {
+            last = Some(rowid);
+            fetched += 1;
+
+            let ip = ip.trim_matches(['[', ']']).parse();
+            let hostname = Some(hostname).filter(|h| !h.is_empty());
+
+            if let Ok(ip) = ip {
+                results.push(Target::new(SocketAddr::new(ip, port), hostname));
+            }
+        }
+
+        // A short page is the last one
+        let next = match fetched >= filter.page_size {
+            true => last.map(|rowid| rowid.to_string()),
+            false => None,
+        };
+
+        Ok((results, next))
+    }
+
+    async fn query(&self, query: &ServerQuery) -> Result<Vec<ServerRecord>> {
+        let mut sql = "SELECT document FROM servers WHERE 1 = 1".to_string();
+        let mut args: Vec<SqlValue> = Vec::new();
+
+        match query.edition.as_deref() {
+            Some("java") => sql.push_str(" AND edition IS NOT 'bedrock'"),
+            Some(edition) => {
+                sql.push_str(" AND edition = ?");
+                args.push(edition.to_string().into());
+            }
+            None => {}
+        }
+        if let Some(license) = query.license {
+            sql.push_str(" AND license = ?");
+            args.push(license.into());
+        }
+        if let Some(version) = &query.version {
+            sql.push_str(
+                " AND instr(lower(json_extract(document, '$.status.version.name')), lower(?)) > 0",
+            );
+            args.push(version.clone().into());
+        }
+        if let Some(motd) = &query.motd {
+            sql.push_str(" AND instr(lower(json_extract(document, '$.motd')), lower(?)) > 0");
+            args.push(motd.clone().into());
+        }
+        if let Some(favicon) = &query.favicon {
+            sql.push_str(" AND json_extract(document, '$.favicon') = ?");
+            args.push(favicon.clone().into());
+        }
+        if let Some(public_key) = &query.public_key {
+            sql.push_str(
+                " AND EXISTS (SELECT 1 FROM json_each(document, '$.publicKeys')
+                WHERE CASE type WHEN 'object' THEN json_extract(value, '$.fingerprint') ELSE value END = ?)",
+            );
+            args.push(public_key.clone().into());
+        }
+        if let Some(auth) = &query.auth {
+            sql.push_str(" AND json_extract(document, '$.auth.mode') = ?");
+            args.push(auth.clone().into());
+        }
+        if let Some(software) = &query.software {
+            sql.push_str(" AND json_extract(document, '$.software.name') = ?");
+            args.push(software.clone().into());
+        }
+        if let Some(mod_id) = &query.mod_id {
+            sql.push_str(" AND EXISTS (SELECT 1 FROM json_each(document, '$.mods') WHERE json_extract(value, '$.id') = ?)");
+            args.push(mod_id.clone().into());
+        }
+        if let Some(min_online) = query.min_online {
+            sql.push_str(" AND json_extract(document, '$.status.players.online') >= ?");
+            args.push(min_online.into());
+        }
+        sql.push_str(" ORDER BY last_seen DESC");
+        if query.limit > 0 {
+            sql.push_str(" LIMIT ?");
+            args.push(query.limit.into());
+        }
+
+        let documents: Vec<String> = self
+            .blocking(move |conn| {
+                let mut statement = conn.prepare(&sql).map_err(Error::other)?;
+                let rows = statement
+                    .query_map(params_from_iter(args), |row| row.get(0))
+                    .map_err(Error::other)?;
+
+                rows.collect::<rusqlite::Result<_>>().map_err(Error::other)
+            })
+            .await?;
+
+        let mut results = Vec::new();
+        for document in documents {
+            results.push(serde_json::from_str(&document)?);
+        }
+
+        Ok(results)
+    }
+
+    async fn add_favicon(&self, favicon: &Favicon) -> Result<()> {
+        let favicon = favicon.clone();
+        let now = DateTime::now().timestamp_millis();
+
+        self.blocking(move |conn| {
+            conn.execute(
+                "INSERT INTO favicons (hash, png, width, height, first_seen, last_seen)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+                ON CONFLICT (hash) DO UPDATE SET last_seen = excluded.last_seen",
+                params![
+                    favicon.hash,
+                    favicon.png,
+                    favicon.width,
+                    favicon.height,
+                    now
+                ],
+            )
+            .map_err(Error::other)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn add_hostname(&self, resolved: &Resolved) -> Result<()> {
+        let (ip, port, hostname) = key(&resolved.target);
+        let srv = resolved.srv;
+        let now = DateTime::now().timestamp_millis();
+
+        self.blocking(move |conn| {
+            conn.execute(
+                "INSERT INTO hostnames (hostname, ip, port, srv, last_resolved)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (hostname, ip, port) DO UPDATE SET
+                    srv = excluded.srv,
+                    last_resolved = excluded.last_resolved",
+                params![hostname, ip, port, srv, now],
+            )
+            .map_err(Error::other)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn add_snapshot(&self, target: &Target, status: &StatusResponse) -> Result<()> {
+        let (ip, port, hostname) = key(target);
+        let now = DateTime::now().timestamp_millis();
+        let (online, max) = (status.players.online, status.players.max);
+        let (version, protocol) = (status.version.name.clone(), status.version.protocol);
+        let description = status.description.plain();
+
+        self.blocking(move |conn| {
+            conn.execute(
+                "INSERT INTO snapshots (ip, port, hostname, timestamp, online, max, version, protocol, description)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![ip, port, hostname, now, online, max, version, protocol, description],
+            )
+            .map_err(Error::other)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// No TTL indexes here, so expired hourly buckets are deleted in the same pass
+    async fn downsample_snapshots(&self) -> Result<()> {
+        let cutoff = downsample_cutoff();
+        let expiry = DateTime::now().timestamp_millis() - retention_days() * 24 * HOUR;
+
+        self.blocking(move |conn| {
+            let tx = conn.transaction().map_err(Error::other)?;
+
+            // Max, version and description come from the latest snapshot of each bucket
+            tx.execute(
+                "INSERT OR REPLACE INTO snapshots_hourly
+                    (ip, port, hostname, hour, avg_online, peak_online, max, version, protocol, description, samples)
+                SELECT b.ip, b.port, b.hostname, b.hour, b.avg_online, b.peak_online,
+                    s.max, s.version, s.protocol, s.description, b.samples
+                FROM (
+                    SELECT ip, port, hostname, timestamp - timestamp % ?2 AS hour,
+                        avg(online) AS avg_online, max(online) AS peak_online,
+                        max(timestamp) AS latest, count(*) AS samples
+                    FROM snapshots WHERE timestamp < ?1
+                    GROUP BY ip, port, hostname, hour
+                ) b
+                JOIN snapshots s
+                    ON s.ip = b.ip AND s.port = b.port AND s.hostname = b.hostname AND s.timestamp = b.latest
+                GROUP BY b.ip, b.port, b.hostname, b.hour",
+                params![cutoff, HOUR],
+            )
+            .map_err(Error::other)?;
+            tx.execute(
+                "DELETE FROM snapshots WHERE timestamp < ?1",
+                params![cutoff],
+            )
+            .map_err(Error::other)?;
+            tx.execute(
+                "DELETE FROM snapshots_hourly WHERE hour < ?1",
+                params![expiry],
+            )
+            .map_err(Error::other)?;
+
+            tx.commit().map_err(Error::other)
+        })
+        .await
+    }
+
+    async fn get_shared_public_keys(&self) -> Result<Vec<(String, i64)>> {
+        self.blocking(|conn| {
+            let mut statement = conn
+                .prepare(
+                    "SELECT json_extract(document, '$.publicKey') AS key, COUNT(*) AS count
+                    FROM servers WHERE key IS NOT NULL
+                    GROUP BY key HAVING count > 1
+                    ORDER BY count DESC",
+                )
+                .map_err(Error::other)?;
+            let rows = statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(Error::other)?;
+
+            rows.collect::<rusqlite::Result<_>>().map_err(Error::other)
+        })
+        .await
+    }
+
+    async fn get_history(&self, target: &Target, since: DateTime) -> Result<Vec<Snapshot>> {
+        let (ip, port, hostname) = key(target);
+        let since = since.timestamp_millis();
+
+        self.blocking(move |conn| {
+            let mut statement = conn
+                .prepare(
+                    "SELECT hour, avg_online, peak_online, max, version, protocol, description
+                    FROM snapshots_hourly WHERE ip = ?1 AND port = ?2 AND hostname = ?3 AND hour >= ?4
+                    UNION ALL
+                    SELECT timestamp, online, online, max, version, protocol, description
+                    FROM snapshots WHERE ip = ?1 AND port = ?2 AND hostname = ?3 AND timestamp >= ?4
+                    ORDER BY 1",
+                )
+                .map_err(Error::other)?;
+            let rows = statement
+                .query_map(params![ip, port, hostname, since], |row| {
+                    Ok(Snapshot {
+                        timestamp: DateTime::from_millis(row.get(0)?),
+                        online: row.get(1)?,
+                        peak_online: row.get(2)?,
+                        max: row.get(3)?,
+                        version: row.get(4)?,
+                        protocol: row.get(5)?,
+                        description: row.get(6)?,
+                    })
+                })
+                .map_err(Error::other)?;
+
+            rows.collect::<rusqlite::Result<_>>().map_err(Error::other)
+        })
+        .await
+    }
+}
+
+fn to_values<T: Serialize>(items: &[T]) -> Result<Vec<Value>> {
+    Ok(items
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<serde_json::Result<_>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, process};
+
+    use futures_util::TryStreamExt;
+
+    use super::*;
+    use crate::database::{get_ips, Store};
+
+    /// Store on a temporary file, removed with its WAL on drop
+    struct TempStore {
+        store: Arc<SqliteStore>,
+        path: PathBuf,
+    }
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("mse-{}-{}.sqlite", process::id(), name));
+            let _ = fs::remove_file(&path);
+
+            TempStore {
+                store: Arc::new(SqliteStore::open(path.to_str().unwrap()).unwrap()),
+                path,
+            }
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.path.clone().into_os_string();
+                path.push(suffix);
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn status(motd: &str, online: i64, players: &[&str]) -> StatusResponse {
+        let sample: Vec<Value> = players
+            .iter()
+            .map(|name| json!({"name": name, "id": format!("id-{}", name)}))
+            .collect();
+
+        serde_json::from_value(json!({
+            "version": {"name": "Paper 1.20.4", "protocol": 765},
+            "players": {"max": 20, "online": online, "sample": sample},
+            "description": {"text": motd}
+        }))
+        .unwrap()
+    }
+
+    fn record(addr: &str, hostname: Option<&str>, status: StatusResponse) -> ServerRecord {
+        let addr: SocketAddr = addr.parse().unwrap();
+
+        serde_json::from_value(json!({
+            "ip": addr.ip().to_string(),
+            "port": addr.port(),
+            "address": addr.to_string(),
+            "hostname": hostname,
+            "license": 0,
+            "publicKeys": [PublicKey::new("key-a".to_string())],
+            "players": status.players.sample,
+            "motd": status.description.plain(),
+            "status": status,
+        }))
+        .unwrap()
+    }
+
+    fn target(addr: &str, hostname: Option<&str>) -> Target {
+        Target::new(addr.parse().unwrap(), hostname.map(str::to_string))
+    }
+
+    async fn all(store: &Arc<SqliteStore>) -> Vec<ServerRecord> {
+        store
+            .query(&ServerQuery {
+                limit: 0,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+    }
+
+    fn names(record: &ServerRecord) -> Vec<&str> {
+        record.players.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn add_merges_into_the_existing_record() {
+        let temp = TempStore::new("merge");
+        let store = &temp.store;
+
+        store
+            .add(&record(
+                "1.2.3.4:25565",
+                None,
+                status("first", 1, &["Steve"]),
+            ))
+            .await
+            .unwrap();
+        let first = all(store).await.remove(0);
+
+        let mut again = record(
+            "1.2.3.4:25565",
+            None,
+            status("second", 2, &["Alex", "Steve"]),
+        );
+        again.public_keys = vec![PublicKey::new("key-b".to_string())];
+        store.add(&again).await.unwrap();
+
+        let mut key_a_again = record("1.2.3.4:25565", None, status("third", 2, &[]));
+        key_a_again.public_keys[0].last_seen = Some(DateTime::from_millis(4_000_000_000_000));
+        store.add(&key_a_again).await.unwrap();
+
+        let records = all(store).await;
+        assert_eq!(records.len(), 1);
+        let merged = &records[0];
+        assert_eq!(merged.motd.as_deref(), Some("third"));
+        assert_eq!(merged.status.players.online, 2);
+        assert_eq!(names(merged), ["Steve", "Alex"]);
+
+        let keys: Vec<&str> = merged
+            .public_keys
+            .iter()
+            .map(|key| key.fingerprint.as_str())
+            .collect();
+        assert_eq!(keys, ["key-a", "key-b"]);
+        // Seeing key-a again only moves its lastSeen
+        let key_a = &merged.public_keys[0];
+        assert_eq!(key_a.first_seen, first.public_keys[0].first_seen);
+        assert_eq!(
+            key_a.last_seen,
+            Some(DateTime::from_millis(4_000_000_000_000))
+        );
+        assert_eq!(merged.first_seen, first.first_seen);
+        assert!(merged.last_seen >= first.last_seen);
+    }
+
+    #[tokio::test]
+    async fn hostnames_are_separate_records() {
+        let temp = TempStore::new("hostnames");
+        let store = &temp.store;
+
+        store
+            .add(&record("1.2.3.4:25565", None, status("raw", 0, &[])))
+            .await
+            .unwrap();
+        store
+            .add(&record(
+                "1.2.3.4:25565",
+                Some("mc.example.com"),
+                status("named", 0, &[]),
+            ))
+            .await
+            .unwrap();
+
+        let records = all(store).await;
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .any(|r| r.hostname.as_deref() == Some("mc.example.com")));
+        assert!(records.iter().any(|r| r.hostname.is_none()));
+    }
+
+    #[tokio::test]
+    async fn update_only_touches_known_records() {
+        let temp = TempStore::new("update");
+        let store = &temp.store;
+
+        store
+            .add(&record(
+                "1.2.3.4:25565",
+                None,
+                status("before", 1, &["Steve"]),
+            ))
+            .await
+            .unwrap();
+
+        store
+            .update(
+                &target("1.2.3.4:25565", None),
+                &status("after", 5, &["Alex"]),
+            )
+            .await
+            .unwrap();
+        store
+            .update(&target("5.6.7.8:25565", None), &status("unknown", 5, &[]))
+            .await
+            .unwrap();
+
+        let records = all(store).await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].motd.as_deref(), Some("after"));
+        assert_eq!(records[0].status.players.online, 5);
+        assert_eq!(names(&records[0]), ["Steve", "Alex"]);
+        // Login results from `add` survive a re-ping
+        assert_eq!(records[0].license, Some(0));
+    }
+
+    #[tokio::test]
+    async fn get_ips_pages_through_matching_records() {
+        let temp = TempStore::new("paging");
+        let store = &temp.store;
+
+        for i in 1..=5 {
+            store
+                .add(&record(
+                    &format!("1.2.3.{}:25565", i),
+                    None,
+                    status("java", 0, &[]),
+                ))
+                .await
+                .unwrap();
+        }
+        store
+            .add(&record(
+                "[2001:db8::1]:25566",
+                Some("v6.example.com"),
+                status("java", 0, &[]),
+            ))
+            .await
+            .unwrap();
+        let mut bedrock = record("9.9.9.9:19132", None, status("bedrock", 0, &[]));
+        bedrock.edition = "bedrock".to_string();
+        store.add(&bedrock).await.unwrap();
+
+        let mut filter = IpFilter::edition("java");
+        filter.page_size = 2;
+
+        let (page, next) = store.get_ips_page(&filter, None).await.unwrap();
+        assert_eq!(page.len(), 2);
+        let (page, _) = store.get_ips_page(&filter, next).await.unwrap();
+        assert_eq!(page[0].addr, "1.2.3.3:25565".parse().unwrap());
+
+        let targets: Vec<Target> = get_ips(store.clone() as Store, filter)
+            .try_collect()
+            .await
+            .unwrap();
+        let addrs: Vec<String> = targets.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            addrs,
+            [
+                "1.2.3.1:25565",
+                "1.2.3.2:25565",
+                "1.2.3.3:25565",
+                "1.2.3.4:25565",
+                "1.2.3.5:25565",
+                "v6.example.com -> [2001:db8::1]:25566",
+            ]
+        );
+
+        let bedrock: Vec<Target> = get_ips(store.clone() as Store, IpFilter::edition("bedrock"))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(bedrock.len(), 1);
+        assert_eq!(bedrock[0].addr.port(), 19132);
+    }
+
+    #[tokio::test]
+    async fn query_filters() {
+        let temp = TempStore::new("query");
+        let store = &temp.store;
+
+        store
+            .add(&record(
+                "1.2.3.4:25565",
+                None,
+                status("A Survival Server", 12, &[]),
+            ))
+            .await
+            .unwrap();
+        let mut online = record("1.2.3.5:25565", None, status("Creative", 3, &[]));
+        online.license = Some(1);
+        online.public_keys = vec![PublicKey::new("key-b".to_string())];
+        store.add(&online).await.unwrap();
+
+        let query = |query: ServerQuery| {
+            let store = store.clone();
+            async move {
+                store
+                    .query(&query)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.ip)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let motd = query(ServerQuery {
+            motd: Some("survival".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(motd.await, ["1.2.3.4"]);
+
+        let license = query(ServerQuery {
+            license: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(license.await, ["1.2.3.5"]);
+
+        let version = query(ServerQuery {
+            version: Some("PAPER".to_string()),
+            edition: Some("java".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(version.await.len(), 2);
+
+        let key = query(ServerQuery {
+            public_key: Some("key-a".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(key.await, ["1.2.3.4"]);
+
+        let min_online = query(ServerQuery {
+            min_online: Some(10),
+            ..Default::default()
+        });
+        assert_eq!(min_online.await, ["1.2.3.4"]);
+
+        let limit = query(ServerQuery {
+            limit: 1,
+            ..Default::default()
+        });
+        assert_eq!(limit.await.len(), 1);
+
+        let bedrock = query(ServerQuery {
+            edition: Some("bedrock".to_string()),
+            ..Default::default()
+        });
+        assert!(bedrock.await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn downsamples_old_snapshots_into_hours() {
+        let temp = TempStore::new("history");
+        let store = &temp.store;
+        let server = target("1.2.3.4:25565", None);
+
+        let now = DateTime::now().timestamp_millis();
+        let old = now - 10 * 24 * HOUR;
+        let hour = old - old % HOUR;
+        let expired = now - 400 * 24 * HOUR;
+
+        {
+            let conn = store.conn.lock().unwrap();
+            for (timestamp, online, version) in [
+                (hour + 60_000, 2, "1.20.1"),
+                (hour + 120_000, 6, "1.20.2"),
+                (hour + HOUR + 60_000, 1, "1.20.2"),
+                (expired, 50, "1.8.8"),
+            ] {
+                conn.execute(
+                    "INSERT INTO snapshots (ip, port, hostname, timestamp, online, max, version, protocol, description)
+                    VALUES ('1.2.3.4', 25565, '', ?1, ?2, 20, ?3, 765, 'motd')",
+                    params![timestamp, online, version],
+                )
+                .unwrap();
+            }
+        }
+        store
+            .add_snapshot(&server, &status("recent", 4, &[]))
+            .await
+            .unwrap();
+
+        store.downsample_snapshots().await.unwrap();
+
+        let history = store
+            .get_history(&server, DateTime::from_millis(0))
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 3);
+
+        assert_eq!(history[0].timestamp.timestamp_millis(), hour);
+        assert_eq!(history[0].online, 4.0);
+        assert_eq!(history[0].peak_online, 6);
+        assert_eq!(history[0].version, "1.20.2");
+
+        assert_eq!(history[1].timestamp.timestamp_millis(), hour + HOUR);
+        assert_eq!(history[1].peak_online, 1);
+
+        // Raw snapshots newer than the cutoff stay as they are
+        assert_eq!(history[2].online, 4.0);
+        assert_eq!(history[2].description, "recent");
+
+        let changes = store
+            .get_version_changes(&server, DateTime::from_millis(0))
+            .await
+            .unwrap();
+        let versions: Vec<&str> = changes.iter().map(|(_, v)| v.as_str()).collect();
+        assert_eq!(versions, ["1.20.2", "Paper 1.20.4"]);
+
+        // Running again doesn't fold anything twice
+        store.downsample_snapshots().await.unwrap();
+        let again = store
+            .get_history(&server, DateTime::from_millis(0))
+            .await
+            .unwrap();
+        assert_eq!(again.len(), 3);
+        assert_eq!(again[0].peak_online, 6);
+    }
+
+    #[tokio::test]
+    async fn reads_and_queries_bare_fingerprints() {
+        let temp = TempStore::new("bare-keys");
+        let store = &temp.store;
+
+        store
+            .add(&record("1.2.3.4:25565", None, status("motd", 0, &[])))
+            .await
+            .unwrap();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE servers SET document = json_set(document, '$.publicKeys', json('[\"old\"]'))",
+                [],
+            )
+            .unwrap();
+
+        let found = store
+            .query(&ServerQuery {
+                public_key: Some("old".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].public_keys[0].fingerprint, "old");
+        assert_eq!(found[0].public_keys[0].first_seen, None);
+
+        // The next scan upgrades the list
+        store
+            .add(&record("1.2.3.4:25565", None, status("motd", 0, &[])))
+            .await
+            .unwrap();
+        let keys = &all(store).await[0].public_keys;
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].fingerprint, "old");
+        assert_eq!(keys[1].fingerprint, "key-a");
+    }
+
+    #[tokio::test]
+    async fn shared_public_keys() {
+        let temp = TempStore::new("keys");
+        let store = &temp.store;
+
+        for addr in ["1.2.3.4:25565", "1.2.3.5:25565", "1.2.3.6:25565"] {
+            let mut record = record(addr, None, status("motd", 0, &[]));
+            record.public_key = Some(match addr {
+                "1.2.3.6:25565" => "other".to_string(),
+                _ => "shared".to_string(),
+            });
+            store.add(&record).await.unwrap();
+        }
+
+        assert_eq!(
+            store.get_shared_public_keys().await.unwrap(),
+            [("shared".to_string(), 2)]
+        );
+    }
+}
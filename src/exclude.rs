@@ -0,0 +1,338 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    net::IpAddr,
+};
+
+use ipnet::{IpNet, Ipv4Net};
+
+/// https://www.iana.org/assignments/iana-ipv4-special-registry/
+const IPV4_SPECIAL_PURPOSE: [&str; 15] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.88.99.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+];
+
+/// https://www.iana.org/assignments/iana-ipv6-special-registry/
+const IPV6_SPECIAL_PURPOSE: [&str; 14] = [
+    "::/128",
+    "::1/128",
+    "::ffff:0:0/96",
+    "64:ff9b:1::/48",
+    "100::/64",
+    "2001::/23",
+    "2001:db8::/32",
+    "2002::/16",
+    "3fff::/20",
+    "5f00::/16",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+
+/// Ranges we never connect to: IANA special-purpose space plus the opt-out blocklist
+pub struct Exclusions {
+    v4: PrefixTrie,
+    v6: PrefixTrie,
+}
+
+impl Exclusions {
+    pub fn new() -> Self {
+        let mut exclusions = Exclusions {
+            v4: PrefixTrie::new(32),
+            v6: PrefixTrie::new(128),
+        };
+
+        for cidr in IPV4_SPECIAL_PURPOSE
+            .iter()
+            .chain(IPV6_SPECIAL_PURPOSE.iter())
+        {
+            exclusions.add(cidr.parse().unwrap());
+        }
+
+        exclusions
+    }
+
+    /// One CIDR or address per line, `#` starts a comment
+    pub fn load_file(&mut self, path: &str) -> io::Result<usize> {
+        let blocklist = fs::read_to_string(path)?;
+        let mut count = 0;
+
+        for (i, line) in blocklist.lines().enumerate() {
+            let cidr = line.split('#').next().unwrap_or("").trim();
+            if cidr.is_empty() {
+                continue;
+            }
+
+            let net = cidr
+                .parse::<IpNet>()
+                .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|e| {
+                    Error::new(ErrorKind::InvalidData, format!("{}:{}: {}", path, i + 1, e))
+                })?;
+
+            self.add(net);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn add(&mut self, net: IpNet) {
+        match net {
+            IpNet::V4(net) => self.v4.insert(v4_key(&net), net.prefix_len()),
+            IpNet::V6(net) => self.v6.insert(u128::from(net.network()), net.prefix_len()),
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => self.v4.contains(u32::from(ip) as u128),
+            // v4-mapped addresses get the v4 blocklist too
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => self.contains(IpAddr::V4(ip)),
+                None => self.v6.contains(u128::from(ip)),
+            },
+        }
+    }
+}
+
+fn v4_key(net: &Ipv4Net) -> u128 {
+    u32::from(net.network()) as u128
+}
+
+/// Binary trie over address bits, nodes in an arena. Lookup is at most one step per bit.
+struct PrefixTrie {
+    nodes: Vec<TrieNode>,
+    bits: u8,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    /// 0 means no child, the root is never a child
+    children: [u32; 2],
+    terminal: bool,
+}
+
+impl PrefixTrie {
+    fn new(bits: u8) -> Self {
+        PrefixTrie {
+            nodes: vec![TrieNode::default()],
+            bits,
+        }
+    }
+
+    fn bit(&self, key: u128, depth: u8) -> usize {
+        ((key >> (self.bits - 1 - depth)) & 1) as usize
+    }
+
+    fn insert(&mut self, key: u128, prefix_len: u8) {
+        let mut node = 0;
+
+        for depth in 0..prefix_len {
+            if self.nodes[node].terminal {
+                return;
+            }
+
+            let bit = self.bit(key, depth);
+            if self.nodes[node].children[bit] == 0 {
+                self.nodes.push(TrieNode::default());
+                self.nodes[node].children[bit] = (self.nodes.len() - 1) as u32;
+            }
+            node = self.nodes[node].children[bit] as usize;
+        }
+
+        // Everything below is covered now
+        self.nodes[node].terminal = true;
+        self.nodes[node].children = [0, 0];
+    }
+
+    fn contains(&self, key: u128) -> bool {
+        let mut node = 0;
+
+        for depth in 0..self.bits {
+            if self.nodes[node].terminal {
+                return true;
+            }
+
+            node = match self.nodes[node].children[self.bit(key, depth)] {
+                0 => return false,
+                child => child as usize,
+            };
+        }
+
+        self.nodes[node].terminal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn only(cidrs: &[&str]) -> Exclusions {
+        let mut exclusions = Exclusions {
+            v4: PrefixTrie::new(32),
+            v6: PrefixTrie::new(128),
+        };
+        for cidr in cidrs {
+            exclusions.add(cidr.parse().unwrap());
+        }
+        exclusions
+    }
+
+    /// Writes `contents` to a file that is removed again on drop
+    struct Blocklist(std::path::PathBuf);
+
+    impl Blocklist {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("mse-{}-{}", process::id(), name));
+            fs::write(&path, contents).unwrap();
+            Blocklist(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for Blocklist {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn matches_prefix_boundaries() {
+        let exclusions = only(&["1.2.3.0/24", "2001:db8::/32"]);
+
+        assert!(exclusions.contains(ip("1.2.3.0")));
+        assert!(exclusions.contains(ip("1.2.3.255")));
+        assert!(!exclusions.contains(ip("1.2.2.255")));
+        assert!(!exclusions.contains(ip("1.2.4.0")));
+
+        assert!(exclusions.contains(ip("2001:db8:ffff::1")));
+        assert!(!exclusions.contains(ip("2001:db9::")));
+    }
+
+    #[test]
+    fn nested_inserts_keep_the_wider_range() {
+        // Narrow first, then the covering range
+        let exclusions = only(&["1.2.3.4/32", "1.2.0.0/16", "1.2.3.0/24"]);
+
+        assert!(exclusions.contains(ip("1.2.3.4")));
+        assert!(exclusions.contains(ip("1.2.200.1")));
+        assert!(!exclusions.contains(ip("1.3.0.0")));
+
+        // Wide first, the narrow one is already covered
+        let exclusions = only(&["1.0.0.0/8", "1.2.3.4/32"]);
+
+        assert!(exclusions.contains(ip("1.2.3.5")));
+        assert!(exclusions.contains(ip("1.255.255.255")));
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges() {
+        let exclusions = only(&[
+            "10.0.0.0/25",
+            "10.0.0.64/26",
+            "10.0.0.128/25",
+            "10.0.2.0/24",
+        ]);
+
+        assert!(exclusions.contains(ip("10.0.0.0")));
+        assert!(exclusions.contains(ip("10.0.0.127")));
+        assert!(exclusions.contains(ip("10.0.0.128")));
+        assert!(exclusions.contains(ip("10.0.0.255")));
+        assert!(!exclusions.contains(ip("10.0.1.0")));
+        assert!(exclusions.contains(ip("10.0.2.42")));
+    }
+
+    #[test]
+    fn whole_address_space() {
+        let exclusions = only(&["0.0.0.0/0"]);
+
+        assert!(exclusions.contains(ip("0.0.0.0")));
+        assert!(exclusions.contains(ip("255.255.255.255")));
+        assert!(!exclusions.contains(ip("::2")));
+    }
+
+    #[test]
+    fn v4_mapped_v6_uses_the_v4_list() {
+        let exclusions = only(&["1.2.3.0/24"]);
+
+        assert!(exclusions.contains(ip("::ffff:1.2.3.4")));
+        assert!(!exclusions.contains(ip("::ffff:1.2.4.4")));
+    }
+
+    #[test]
+    fn special_purpose_ranges() {
+        let exclusions = Exclusions::new();
+
+        for excluded in [
+            "10.1.2.3",
+            "127.0.0.1",
+            "192.168.1.1",
+            "224.0.0.1",
+            "::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(exclusions.contains(ip(excluded)), "{}", excluded);
+        }
+        for allowed in ["8.8.8.8", "1.1.1.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(!exclusions.contains(ip(allowed)), "{}", allowed);
+        }
+    }
+
+    #[test]
+    fn loads_blocklist_with_comments() {
+        let file = Blocklist::new(
+            "blocklist.txt",
+            "# opt-outs\n\n1.2.3.0/24 # someone\n  5.6.7.8  \n2a00::/16\n#9.9.9.9\n",
+        );
+        let mut exclusions = Exclusions::new();
+
+        assert_eq!(exclusions.load_file(file.path()).unwrap(), 3);
+        assert!(exclusions.contains(ip("1.2.3.9")));
+        assert!(exclusions.contains(ip("5.6.7.8")));
+        assert!(!exclusions.contains(ip("5.6.7.9")));
+        assert!(exclusions.contains(ip("2a00:1::1")));
+        assert!(!exclusions.contains(ip("9.9.9.9")));
+    }
+
+    #[test]
+    fn blocklist_errors_name_the_line() {
+        let file = Blocklist::new("invalid.txt", "1.2.3.0/24\n\nnot-an-ip\n");
+
+        let error = Exclusions::new().load_file(file.path()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains(&format!("{}:3:", file.path())));
+
+        let file = Blocklist::new("prefix.txt", "1.2.3.0/33\n");
+        assert!(Exclusions::new().load_file(file.path()).is_err());
+
+        let error = Exclusions::new()
+            .load_file("/nonexistent/blocklist.txt")
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::{Error, ErrorKind, Result};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::model::StatusResponse;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Vanilla only accepts 64x64, modded and proxy servers send other sizes
+const MAX_SIZE: u32 = 1024;
+
+/// A decoded server icon, stored once per distinct image
+#[derive(Debug, Clone)]
+pub struct Favicon {
+    /// SHA-256 of the PNG bytes, hex
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+    pub png: Vec<u8>,
+}
+
+impl Favicon {
+    /// `data:image/png;base64,...`. Old servers wrap the base64 in newlines.
+    pub fn from_data_uri(uri: &str) -> Result<Self> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+        let (header, data) = uri
+            .split_once(',')
+            .ok_or_else(|| invalid("Not a data URI"))?;
+        if !header.starts_with("data:image/png") || !header.ends_with(";base64") {
+            return Err(invalid("Not a base64 PNG"));
+        }
+
+        let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+        let png = STANDARD
+            .decode(data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let (width, height) = dimensions(&png)?;
+
+        Ok(Favicon {
+            hash: Sha256::digest(&png)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            width,
+            height,
+            png,
+        })
+    }
+
+    /// Removes `favicon` from the status. Invalid icons are dropped as well.
+    pub fn take(status: &mut StatusResponse) -> Option<Self> {
+        let uri = status.extra.remove("favicon")?;
+
+        Self::from_data_uri(uri.as_str()?).ok()
+    }
+}
+
+/// Width and height from the IHDR chunk, which has to come first
+fn dimensions(png: &[u8]) -> Result<(u32, u32)> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    if png.len() < 24 || png[..8] != PNG_SIGNATURE {
+        return Err(invalid("Not a PNG"));
+    }
+    if &png[12..16] != b"IHDR" {
+        return Err(invalid("Missing IHDR"));
+    }
+
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+        return Err(invalid("Bad favicon dimensions"));
+    }
+
+    Ok((width, height))
+}
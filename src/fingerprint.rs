@@ -0,0 +1,411 @@
+use serde_json::Value;
+
+use crate::model::{AuthMode, Software, StatusResponse};
+
+/// Protocol version sent in the status handshake. Proxies echo it back when
+/// they support it, backends answer with their own.
+pub const STATUS_PROTOCOL: i64 = 765;
+
+/// Substrings of `version.name` (lowercased) and what they point at
+const VERSION_PATTERNS: [(&str, &str, f64); 22] = [
+    ("paper", "paper", 0.9),
+    ("purpur", "purpur", 0.95),
+    ("pufferfish", "pufferfish", 0.95),
+    ("folia", "folia", 0.95),
+    ("leaves", "leaves", 0.9),
+    ("spigot", "spigot", 0.9),
+    ("craftbukkit", "craftbukkit", 0.9),
+    ("mohist", "mohist", 0.95),
+    ("arclight", "arclight", 0.95),
+    ("magma", "magma", 0.9),
+    ("catserver", "catserver", 0.95),
+    ("sponge", "sponge", 0.9),
+    ("neoforge", "neoforge", 0.95),
+    ("forge", "forge", 0.8),
+    ("fabric", "fabric", 0.8),
+    ("velocity", "velocity", 0.95),
+    ("bungeecord", "bungeecord", 0.95),
+    ("waterfall", "waterfall", 0.95),
+    ("flamecord", "flamecord", 0.95),
+    ("travertine", "travertine", 0.95),
+    ("geyser", "geyser", 0.9),
+    ("tcpshield", "tcpshield", 0.9),
+];
+
+/// Bedrock `levelName` / MOTD markers
+const BEDROCK_PATTERNS: [(&str, &str, f64); 4] = [
+    ("geyser", "geyser", 0.9),
+    ("pocketmine", "pocketmine", 0.9),
+    ("nukkit", "nukkit", 0.9),
+    ("bedrock level", "bedrock", 0.6),
+];
+
+/// How the login probe broke off when it didn't end in a known packet.
+/// Timeouts and refused connections say nothing about the software.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// A packet id that has no meaning during login
+    UnexpectedPacket(i32),
+    /// Hung up mid-packet or sent bytes that don't frame as a packet, like a
+    /// pre-1.7 kick
+    Malformed,
+}
+
+/// Everything the scan learned about a server
+pub struct Signals<'a> {
+    pub status: &'a StatusResponse,
+    /// Same meaning as `ServerRecord::license`
+    pub license: Option<i64>,
+    pub auth: Option<&'a AuthMode>,
+    /// Login Plugin Request channels
+    pub channels: &'a [String],
+    pub protocol_error: Option<ProtocolError>,
+}
+
+/// Accumulates weighted hints per software. Independent hints for the same
+/// software combine as `1 - (1 - a)(1 - b)`.
+#[derive(Default)]
+struct Scores(Vec<(&'static str, f64, Vec<String>)>);
+
+impl Scores {
+    fn add(&mut self, name: &'static str, weight: f64, evidence: impl Into<String>) {
+        match self.0.iter_mut().find(|(n, _, _)| *n == name) {
+            Some((_, confidence, reasons)) => {
+                *confidence = 1.0 - (1.0 - *confidence) * (1.0 - weight);
+                reasons.push(evidence.into());
+            }
+            None => self.0.push((name, weight, vec![evidence.into()])),
+        }
+    }
+
+    fn best(self) -> Software {
+        self.0
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(name, confidence, evidence)| Software {
+                name: name.to_string(),
+                confidence: (confidence * 100.0).round() / 100.0,
+                evidence,
+            })
+            .unwrap_or_else(|| Software {
+                name: "unknown".to_string(),
+                confidence: 0.0,
+                evidence: Vec::new(),
+            })
+    }
+}
+
+/// Java servers: status quirks, version name, protocol behaviour and login
+pub fn classify(signals: &Signals) -> Software {
+    let status = signals.status;
+    let mut scores = Scores::default();
+
+    let name = status.version.name.to_lowercase();
+    for (pattern, software, weight) in VERSION_PATTERNS {
+        if name.contains(pattern) {
+            scores.add(
+                software,
+                weight,
+                format!("version name contains \"{}\"", pattern),
+            );
+        }
+    }
+
+    match status.extra.get("modinfo") {
+        Some(modinfo) if modinfo["type"].as_str() == Some("FML") => {
+            scores.add("forge", 0.9, "modinfo type FML")
+        }
+        Some(_) => scores.add("forge", 0.5, "modinfo present"),
+        None => {}
+    }
+    if status.extra.contains_key("forgeData") {
+        scores.add("forge", 0.9, "forgeData present");
+    }
+
+    // NoChatReports on Fabric, FreedomChat on Paper
+    if status.extra.get("preventsChatReports") == Some(&Value::Bool(true)) {
+        scores.add("fabric", 0.3, "preventsChatReports set");
+    }
+
+    // Pre-1.7 servers only answer the legacy ping, which has no JSON, and
+    // can't follow a modern login either
+    if status.key_order.is_empty() {
+        scores.add("legacy", 0.6, "legacy ping only");
+        if signals.protocol_error.is_some() {
+            scores.add("legacy", 0.5, "modern login not understood");
+        }
+        return scores.best();
+    }
+
+    // Gson-serialized ServerPing objects (BungeeCord, Velocity) start with
+    // `version`, vanilla's codec starts with `description`
+    match status.key_order.first().map(String::as_str) {
+        Some("version") => scores.add("proxy", 0.4, "status starts with version"),
+        Some("description") if is_release(&name) => {
+            scores.add("vanilla", 0.3, "status starts with description")
+        }
+        _ => {}
+    }
+
+    if status.extra.contains_key("enforcesSecureChat") && is_release(&name) {
+        scores.add("vanilla", 0.3, "enforcesSecureChat present");
+    }
+
+    if status.version.protocol == STATUS_PROTOCOL && is_range(&name) {
+        scores.add("proxy", 0.5, "protocol echoed with a version range");
+    } else if status.version.protocol == STATUS_PROTOCOL
+        && is_release(&name)
+        && !name.starts_with("1.20.3")
+        && !name.starts_with("1.20.4")
+    {
+        scores.add("honeypot", 0.3, "protocol echoed with a fixed version");
+    }
+
+    match signals.license {
+        Some(-1) if status.key_order.first().map(String::as_str) == Some("version") => {
+            scores.add("proxy", 0.2, "login refused")
+        }
+        Some(0) if is_release(&name) => scores.add("vanilla", 0.1, "offline mode"),
+        _ => {}
+    }
+
+    // Real servers answer a JSON status with login packets they can parse
+    // themselves; fakes that only mimic the status don't
+    match signals.protocol_error {
+        Some(ProtocolError::UnexpectedPacket(id)) => scores.add(
+            "honeypot",
+            0.5,
+            format!("unexpected packet {:#04x} during login", id),
+        ),
+        Some(ProtocolError::Malformed) => scores.add("honeypot", 0.3, "malformed login answer"),
+        None => {}
+    }
+
+    // A backend that expects forwarded player info, usually Paper behind
+    // BungeeCord or Velocity
+    if signals.auth == Some(&AuthMode::ProxyForwarding) {
+        scores.add("paper", 0.3, "login requires proxy forwarding");
+    }
+
+    for channel in signals.channels {
+        match channel.split(':').next() {
+            Some("velocity") => scores.add("paper", 0.4, format!("{} requested", channel)),
+            Some("fml") | Some("forge") => scores.add("forge", 0.8, "FML login handshake"),
+            Some("neoforge") => scores.add("neoforge", 0.9, "NeoForge login handshake"),
+            Some("fabric") | Some("fabric-networking-api-v1") => {
+                scores.add("fabric", 0.8, "Fabric login query")
+            }
+            _ => {}
+        }
+    }
+
+    scores.best()
+}
+
+/// Bedrock pongs carry little more than the level name
+pub fn classify_bedrock(status: &StatusResponse) -> Software {
+    let mut scores = Scores::default();
+
+    let level_name = status
+        .extra
+        .get("levelName")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_lowercase();
+    let motd = status.description.plain().to_lowercase();
+    for (pattern, software, weight) in BEDROCK_PATTERNS {
+        if level_name.contains(pattern) {
+            scores.add(
+                software,
+                weight,
+                format!("level name contains \"{}\"", pattern),
+            );
+        } else if motd.contains(pattern) {
+            scores.add(
+                software,
+                weight / 2.0,
+                format!("MOTD contains \"{}\"", pattern),
+            );
+        }
+    }
+
+    scores.best()
+}
+
+/// `1.20.4`, `1.8`
+fn is_release(name: &str) -> bool {
+    !name.is_empty() && name.split('.').all(|part| part.parse::<u32>().is_ok())
+}
+
+/// `1.8.x-1.20.x`, `1.8 - 1.20`
+fn is_range(name: &str) -> bool {
+    name.contains(".x") || name.split('-').filter(|part| part.contains('.')).count() > 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signals(status: &StatusResponse) -> Signals<'_> {
+        Signals {
+            status,
+            license: None,
+            auth: None,
+            channels: &[],
+            protocol_error: None,
+        }
+    }
+
+    fn parse(json: &str) -> StatusResponse {
+        StatusResponse::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn paper() {
+        let status = parse(
+            r#"{"description":{"text":"A Minecraft Server"},"players":{"max":20,"online":0},
+            "version":{"name":"Paper 1.20.4","protocol":765},"enforcesSecureChat":true}"#,
+        );
+        let software = classify(&Signals {
+            auth: Some(&AuthMode::ProxyForwarding),
+            ..signals(&status)
+        });
+
+        assert_eq!(software.name, "paper");
+        assert_eq!(software.confidence, 0.93);
+        assert_eq!(
+            software.evidence,
+            [
+                "version name contains \"paper\"",
+                "login requires proxy forwarding"
+            ]
+        );
+    }
+
+    #[test]
+    fn velocity_range() {
+        let status = parse(
+            r#"{"version":{"name":"Velocity 3.3.0","protocol":765},
+            "players":{"max":500,"online":12},"description":{"text":"Lobby"}}"#,
+        );
+        assert_eq!(classify(&signals(&status)).name, "velocity");
+
+        // Without a name the echoed protocol and field order still give it away
+        let status = parse(
+            r#"{"version":{"name":"1.8.x-1.21.x","protocol":765},
+            "players":{"max":500,"online":12},"description":{"text":"Lobby"}}"#,
+        );
+        let software = classify(&Signals {
+            license: Some(-1),
+            ..signals(&status)
+        });
+        assert_eq!(software.name, "proxy");
+        assert_eq!(software.confidence, 0.76);
+    }
+
+    #[test]
+    fn forge_data() {
+        let status = parse(
+            r#"{"description":{"text":"Modded"},"players":{"max":20,"online":1},
+            "version":{"name":"1.20.1","protocol":763},
+            "forgeData":{"channels":[],"mods":[],"fmlNetworkVersion":3}}"#,
+        );
+        let software = classify(&Signals {
+            channels: &["fml:loginwrapper".to_string()],
+            ..signals(&status)
+        });
+
+        assert_eq!(software.name, "forge");
+        assert_eq!(software.confidence, 0.98);
+        assert_eq!(
+            software.evidence,
+            ["forgeData present", "FML login handshake"]
+        );
+    }
+
+    #[test]
+    fn legacy() {
+        // Legacy pings have no JSON, so no key order either
+        let mut status = parse(
+            r#"{"version":{"name":"1.6.4","protocol":78},
+            "players":{"max":20,"online":0},"description":{"text":"Old"}}"#,
+        );
+        status.key_order.clear();
+
+        let software = classify(&signals(&status));
+        assert_eq!(software.name, "legacy");
+        assert_eq!(software.confidence, 0.6);
+
+        let software = classify(&Signals {
+            protocol_error: Some(ProtocolError::Malformed),
+            ..signals(&status)
+        });
+        assert_eq!(software.confidence, 0.8);
+        assert_eq!(
+            software.evidence,
+            ["legacy ping only", "modern login not understood"]
+        );
+    }
+
+    #[test]
+    fn protocol_errors_point_at_fakes() {
+        let status = parse(
+            r#"{"description":{"text":"Free ranks"},"players":{"max":100,"online":3},
+            "version":{"name":"1.20.4","protocol":765}}"#,
+        );
+
+        let software = classify(&Signals {
+            protocol_error: Some(ProtocolError::UnexpectedPacket(0x26)),
+            ..signals(&status)
+        });
+        assert_eq!(software.name, "honeypot");
+        assert_eq!(software.evidence, ["unexpected packet 0x26 during login"]);
+
+        let software = classify(&Signals {
+            protocol_error: Some(ProtocolError::Malformed),
+            ..signals(&status)
+        });
+        assert!(software
+            .evidence
+            .contains(&"malformed login answer".to_string()));
+    }
+
+    #[test]
+    fn unknown_without_hints() {
+        let status = parse(
+            r#"{"players":{"max":20,"online":0},"version":{"name":"Server","protocol":47},"description":""}"#,
+        );
+        let software = classify(&signals(&status));
+
+        assert_eq!(software.name, "unknown");
+        assert_eq!(software.confidence, 0.0);
+        assert!(software.evidence.is_empty());
+    }
+
+    #[test]
+    fn bedrock() {
+        let geyser = parse(
+            r#"{"version":{"name":"1.21.2","protocol":686},"players":{"max":100,"online":4},
+            "description":{"text":"Survival"},"levelName":"Geyser"}"#,
+        );
+        let software = classify_bedrock(&geyser);
+        assert_eq!(software.name, "geyser");
+        assert_eq!(software.confidence, 0.9);
+
+        // Only the MOTD mentions it, half the weight
+        let nukkit = parse(
+            r#"{"version":{"name":"1.21.2","protocol":686},"players":{"max":100,"online":4},
+            "description":{"text":"Powered by Nukkit"},"levelName":"world"}"#,
+        );
+        let software = classify_bedrock(&nukkit);
+        assert_eq!(software.name, "nukkit");
+        assert_eq!(software.confidence, 0.45);
+        assert_eq!(software.evidence, ["MOTD contains \"nukkit\""]);
+
+        let vanilla = parse(
+            r#"{"version":{"name":"1.21.2","protocol":686},"players":{"max":10,"online":0},
+            "description":{"text":"Dedicated Server"},"levelName":"Bedrock level"}"#,
+        );
+        assert_eq!(classify_bedrock(&vanilla).name, "bedrock");
+    }
+}
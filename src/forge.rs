@@ -0,0 +1,195 @@
+use std::io::{Cursor, Error, ErrorKind, Read, Result};
+
+use minecraft_protocol::types::var_int::VarInt;
+use serde_json::{json, Value};
+
+use crate::model::StatusResponse;
+
+/// Loader name and normalized `[{id, version}]` from `modinfo` (FML1) or `forgeData` (FML2/3)
+pub fn parse_mods(status: &StatusResponse) -> Option<(String, Vec<Value>)> {
+    if let Some(modinfo) = status.extra.get("modinfo").and_then(Value::as_object) {
+        let mods = modinfo["modList"]
+            .as_array()
+            .map(|mods| {
+                mods.iter()
+                    .map(|m| mod_entry(&m["modid"], &m["version"]))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let loader = modinfo["type"].as_str().unwrap_or("fml").to_lowercase();
+
+        return Some((loader, mods));
+    }
+
+    let forge_data = status.extra.get("forgeData")?;
+
+    let mods = match forge_data["d"].as_str() {
+        Some(d) => decode_mods(d).unwrap_or_default(),
+        None => forge_data["mods"]
+            .as_array()
+            .map(|mods| {
+                mods.iter()
+                    .map(|m| mod_entry(&m["modId"], &m["modmarker"]))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    Some(("forge".to_string(), mods))
+}
+
+fn mod_entry(id: &Value, version: &Value) -> Value {
+    json!({"id": id, "version": version})
+}
+
+/// FML3 packs the mod list into `d`, see net.minecraftforge.network.ServerStatusPing
+fn decode_mods(d: &str) -> Result<Vec<Value>> {
+    let buf = decode_optimized(d)?;
+    let mut reader = Cursor::new(&buf[..]);
+
+    let _truncated = read_u8(&mut reader)? != 0;
+    let mods_size = u16::from_be_bytes([read_u8(&mut reader)?, read_u8(&mut reader)?]);
+
+    let mut mods = Vec::new();
+    for _ in 0..mods_size {
+        let channel_size_and_version_flag = VarInt::read_sync(&mut reader)?.0;
+        let channel_size = channel_size_and_version_flag >> 1;
+        let ignore_server_only = channel_size_and_version_flag & 0b1 != 0;
+
+        let id = read_string(&mut reader)?;
+        let version = match ignore_server_only {
+            true => None,
+            false => Some(read_string(&mut reader)?),
+        };
+
+        for _ in 0..channel_size {
+            let _channel_name = read_string(&mut reader)?;
+            let _channel_version = read_string(&mut reader)?;
+            let _required_on_client = read_u8(&mut reader)?;
+        }
+
+        mods.push(mod_entry(&json!(id), &json!(version)));
+    }
+
+    Ok(mods)
+}
+
+/// Every char carries 15 bits; the first two chars hold the byte length
+fn decode_optimized(d: &str) -> Result<Vec<u8>> {
+    let chars: Vec<u32> = d.chars().map(|c| c as u32).collect();
+    if chars.len() < 2 {
+        return Err(Error::new(ErrorKind::InvalidData, "Truncated forge data"));
+    }
+
+    // The length is attacker controlled, it can't claim more than the chars carry
+    let size = ((chars[0] & 0x7FFF) | ((chars[1] & 0x7FFF) << 15)) as usize;
+    if chars[0] > 0x7FFF || chars[1] > 0x7FFF || size > (chars.len() - 2) * 15 / 8 {
+        return Err(Error::new(ErrorKind::InvalidData, "Bad forge data length"));
+    }
+    let mut buf = Vec::with_capacity(size);
+
+    let mut buffer: u32 = 0;
+    let mut bits_in_buf = 0;
+    for c in &chars[2..] {
+        while bits_in_buf >= 8 {
+            buf.push(buffer as u8);
+            buffer >>= 8;
+            bits_in_buf -= 8;
+        }
+
+        buffer |= (c & 0x7FFF) << bits_in_buf;
+        bits_in_buf += 15;
+    }
+
+    while buf.len() < size {
+        buf.push(buffer as u8);
+        buffer >>= 8;
+    }
+
+    Ok(buf)
+}
+
+fn read_u8(reader: &mut Cursor<&[u8]>) -> Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+
+    Ok(buf[0])
+}
+
+fn read_string(reader: &mut Cursor<&[u8]>) -> Result<String> {
+    let len = VarInt::read_sync(reader)?.0;
+    let remaining = reader.get_ref().len() as u64 - reader.position();
+    if len < 0 || len as u64 > remaining {
+        return Err(Error::new(ErrorKind::InvalidData, "Bad string length"));
+    }
+
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf)?;
+
+    String::from_utf8(buf).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `d` for minecraft 1.20.1, forge 47.2.0 (two channels) and jei with
+    /// IGNORE_SERVER_VERSION, encoded the way ServerStatusPing does
+    const FML3_D: &str = "\u{46}\u{0}\u{0}\u{6}\u{3424}\u{734b}\u{3656}\u{2e4c}\u{1998}\u{33a}\u{2e31}\u{6064}\u{44b8}\u{2821}\u{7660}\u{6e4d}\u{1959}\u{1a03}\u{2e37}\u{5c64}\u{30c0}\u{4ba0}\u{2656}\u{6bee}\u{1bdc}\u{3a39}\u{6e69}\u{6ce}\u{38c4}\u{981}\u{3050}\u{e0e}\u{1a5b}\u{1ba}\u{2e31}\u{262}\u{c04}\u{2b50}\u{696}\u{0}";
+
+    #[test]
+    fn decodes_fml3_mod_list() {
+        let mods = decode_mods(FML3_D).unwrap();
+
+        assert_eq!(
+            mods,
+            vec![
+                json!({"id": "minecraft", "version": "1.20.1"}),
+                json!({"id": "forge", "version": "47.2.0"}),
+                json!({"id": "jei", "version": null}),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_mods_reads_forge_data() {
+        let status = StatusResponse {
+            extra: [("forgeData".to_string(), json!({"d": FML3_D}))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        let (loader, mods) = parse_mods(&status).unwrap();
+        assert_eq!(loader, "forge");
+        assert_eq!(mods.len(), 3);
+    }
+
+    #[test]
+    fn rejects_length_larger_than_payload() {
+        // Claims 2^30 - 1 bytes with nothing behind it
+        assert!(decode_optimized("\u{7fff}\u{7fff}").is_err());
+        assert!(decode_optimized("\u{7fff}\u{7fff}\u{1}\u{1}").is_err());
+        // Out of range length chars
+        assert!(decode_optimized("\u{10000}\u{0}\u{1}").is_err());
+        assert!(decode_optimized("\u{1}").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_string_length() {
+        // VarInt -1
+        let data = [0xff, 0xff, 0xff, 0xff, 0x0f];
+        assert!(read_string(&mut Cursor::new(&data[..])).is_err());
+
+        // 5 bytes claimed, 2 present
+        let data = [0x05, b'a', b'b'];
+        assert!(read_string(&mut Cursor::new(&data[..])).is_err());
+    }
+
+    #[test]
+    fn truncated_mod_list_is_an_error() {
+        let truncated: String = FML3_D.chars().take(20).collect();
+        assert!(decode_mods(&truncated).is_err());
+    }
+}
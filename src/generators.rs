@@ -0,0 +1,616 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    pin::pin,
+};
+
+use futures_util::StreamExt;
+use ipnet::Ipv4Net;
+use rand::Rng;
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    database::{get_ips, IpFilter, Store},
+    target::Target,
+    utils::get_random_ip,
+};
+
+pub trait TargetGenerator: Send {
+    /// None once the generator is exhausted
+    fn next_ip(&mut self) -> Option<IpAddr>;
+}
+
+/// Where the targets of one `TARGETS` entry come from
+pub enum Source {
+    /// Each address is tried on every port
+    Addresses(Box<dyn TargetGenerator>, Vec<u16>),
+    /// Every Java record already in the database, on its stored port and hostname
+    Known(Store),
+}
+
+/// `TARGETS` entries, separated by `;`, each optionally followed by `@ports`
+///
+/// - `random`
+/// - `permutation:1.0.0.0/8,2.0.0.0/8`
+/// - `sequential:1.0.0.0/8,2.0.0.0/8@25565,25566-25600`
+/// - `file:/path/to/ips.txt` (`file:-` for stdin)
+/// - `known`
+///
+/// Entries without `@ports` use `default_ports`. `known` keeps the stored ports.
+pub fn from_spec(spec: &str, default_ports: &[u16], db: &Store) -> io::Result<Source> {
+    let (spec, ports) = match spec.rsplit_once('@') {
+        Some((spec, ports)) => (spec, parse_ports(ports)?),
+        None => (spec, default_ports.to_vec()),
+    };
+    let (kind, args) = spec.split_once(':').unwrap_or((spec, ""));
+
+    let generator: Box<dyn TargetGenerator> = match kind.trim() {
+        "random" => Box::new(UniformRandom),
+        "permutation" => Box::new(RandomPermutation::new(&parse_cidrs(args)?)),
+        "sequential" => Box::new(Sequential::new(&parse_cidrs(args)?)),
+        "file" => Box::new(FileTargets::open(args.trim())?),
+        "known" => return Ok(Source::Known(db.clone())),
+        kind => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown target generator: {}", kind),
+            ))
+        }
+    };
+
+    Ok(Source::Addresses(generator, ports))
+}
+
+/// `25565,25566-25600`
+pub fn parse_ports(ports: &str) -> io::Result<Vec<u16>> {
+    let invalid = |e| Error::new(ErrorKind::InvalidInput, e);
+    let mut result = Vec::new();
+
+    for part in ports.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: u16 = start.trim().parse().map_err(invalid)?;
+                let end: u16 = end.trim().parse().map_err(invalid)?;
+                result.extend(start..=end);
+            }
+            None => result.push(part.parse().map_err(invalid)?),
+        }
+    }
+
+    result.sort();
+    result.dedup();
+
+    if result.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "No ports"));
+    }
+
+    Ok(result)
+}
+
+fn parse_cidrs(cidrs: &str) -> io::Result<Vec<Ipv4Net>> {
+    cidrs
+        .split(',')
+        .map(|cidr| cidr.trim())
+        .filter(|cidr| !cidr.is_empty())
+        .map(|cidr| {
+            cidr.parse::<Ipv4Net>()
+                .or_else(|_| cidr.parse::<Ipv4Addr>().map(Ipv4Net::from))
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+        })
+        .collect()
+}
+
+/// Drives a generator on a blocking thread, since file and stdin reads block.
+/// Known hosts are streamed from the store a page at a time.
+pub fn spawn(source: Source, tx: Sender<Target>) {
+    match source {
+        Source::Addresses(mut generator, ports) => {
+            tokio::task::spawn_blocking(move || {
+                while let Some(ip) = generator.next_ip() {
+                    for port in &ports {
+                        let target = Target::from(SocketAddr::new(ip, *port));
+                        if tx.blocking_send(target).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        Source::Known(db) => {
+            tokio::spawn(async move {
+                let mut targets = pin!(get_ips(db, IpFilter::edition("java")));
+
+                while let Some(target) = targets.next().await {
+                    let target = match target {
+                        Ok(target) => target,
+                        Err(e) => {
+                            eprintln!("Known hosts: {}", e);
+                            return;
+                        }
+                    };
+                    if tx.send(target).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Merged, sorted `[start, end)` ranges so overlapping CIDRs don't yield an address twice
+fn merge_ranges(cidrs: &[Ipv4Net]) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = cidrs
+        .iter()
+        .map(|net| {
+            let start = u32::from(net.network()) as u64;
+            (start, start + (1u64 << (32 - net.prefix_len())))
+        })
+        .collect();
+    ranges.sort();
+
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// Uniform over all of IPv4, repeats allowed
+pub struct UniformRandom;
+
+impl TargetGenerator for UniformRandom {
+    fn next_ip(&mut self) -> Option<IpAddr> {
+        Some(get_random_ip())
+    }
+}
+
+/// Visits every address of the CIDR set exactly once in random order.
+///
+/// Walks the cyclic group Z*_p for the smallest prime p > N: starting anywhere and
+/// repeatedly multiplying by a primitive root g visits every element of 1..p once.
+/// Elements above N are skipped, which is rare since p is close to N.
+pub struct RandomPermutation {
+    ranges: Vec<(u64, u64)>,
+    total: u64,
+    prime: u64,
+    generator: u64,
+    start: u64,
+    current: u64,
+    done: bool,
+}
+
+impl RandomPermutation {
+    pub fn new(cidrs: &[Ipv4Net]) -> Self {
+        let ranges = merge_ranges(cidrs);
+        let total = ranges.iter().map(|(start, end)| end - start).sum();
+
+        let prime = next_prime(total + 1);
+        let generator = primitive_root(prime);
+        let start = match prime {
+            2 => 1,
+            _ => rand::thread_rng().gen_range(1..prime),
+        };
+
+        RandomPermutation {
+            ranges,
+            total,
+            prime,
+            generator,
+            start,
+            current: start,
+            done: total == 0,
+        }
+    }
+
+    fn index_to_ip(&self, mut index: u64) -> IpAddr {
+        for (start, end) in &self.ranges {
+            if index < end - start {
+                return IpAddr::V4(Ipv4Addr::from((start + index) as u32));
+            }
+            index -= end - start;
+        }
+
+        unreachable!("index out of range")
+    }
+}
+
+impl TargetGenerator for RandomPermutation {
+    fn next_ip(&mut self) -> Option<IpAddr> {
+        while !self.done {
+            let element = self.current;
+
+            self.current = mul_mod(self.current, self.generator, self.prime);
+            if self.current == self.start {
+                self.done = true;
+            }
+
+            if element <= self.total {
+                return Some(self.index_to_ip(element - 1));
+            }
+        }
+
+        None
+    }
+}
+
+/// Address by address, CIDR by CIDR
+pub struct Sequential {
+    ranges: Vec<(u64, u64)>,
+    range: usize,
+    next: u64,
+}
+
+impl Sequential {
+    pub fn new(cidrs: &[Ipv4Net]) -> Self {
+        let ranges = merge_ranges(cidrs);
+        let next = ranges.first().map(|(start, _)| *start).unwrap_or(0);
+
+        Sequential {
+            ranges,
+            range: 0,
+            next,
+        }
+    }
+}
+
+impl TargetGenerator for Sequential {
+    fn next_ip(&mut self) -> Option<IpAddr> {
+        let (_, end) = *self.ranges.get(self.range)?;
+
+        let ip = IpAddr::V4(Ipv4Addr::from(self.next as u32));
+
+        self.next += 1;
+        if self.next >= end {
+            self.range += 1;
+            if let Some((start, _)) = self.ranges.get(self.range) {
+                self.next = *start;
+            }
+        }
+
+        Some(ip)
+    }
+}
+
+/// One address per line, `#` starts a comment
+pub struct FileTargets {
+    lines: Box<dyn BufRead + Send>,
+}
+
+impl FileTargets {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let lines: Box<dyn BufRead + Send> = match path {
+            "-" | "" => Box::new(BufReader::new(io::stdin())),
+            path => Box::new(BufReader::new(File::open(path)?)),
+        };
+
+        Ok(FileTargets { lines })
+    }
+}
+
+impl TargetGenerator for FileTargets {
+    fn next_ip(&mut self) -> Option<IpAddr> {
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            if self.lines.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+
+            let target = line.split('#').next().unwrap_or("").trim();
+            if target.is_empty() {
+                continue;
+            }
+
+            match target.parse() {
+                Ok(ip) => return Some(ip),
+                Err(_) => eprintln!("Invalid target: {}", target),
+            }
+        }
+    }
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+
+    result
+}
+
+/// Deterministic Miller-Rabin for u64
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+    if n < 2 {
+        return false;
+    }
+    for p in BASES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    let mut d = n - 1;
+    let mut r = 0;
+    while d.is_multiple_of(2) {
+        d /= 2;
+        r += 1;
+    }
+
+    'witness: for a in BASES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..r {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+
+    true
+}
+
+fn next_prime(mut n: u64) -> u64 {
+    while !is_prime(n) {
+        n += 1;
+    }
+
+    n
+}
+
+fn prime_factors(mut n: u64) -> Vec<u64> {
+    let mut factors = Vec::new();
+
+    let mut p = 2;
+    while p * p <= n {
+        if n.is_multiple_of(p) {
+            factors.push(p);
+            while n.is_multiple_of(p) {
+                n /= p;
+            }
+        }
+        p += 1;
+    }
+    if n > 1 {
+        factors.push(n);
+    }
+
+    factors
+}
+
+/// Random g whose order is p - 1, so g^k cycles through all of Z*_p
+fn primitive_root(prime: u64) -> u64 {
+    if prime == 2 {
+        return 1;
+    }
+
+    let factors = prime_factors(prime - 1);
+    let mut rng = rand::thread_rng();
+
+    loop {
+        let g = rng.gen_range(2..prime);
+
+        if factors
+            .iter()
+            .all(|q| pow_mod(g, (prime - 1) / q, prime) != 1)
+        {
+            return g;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::database::SqliteStore;
+
+    fn cidrs(cidrs: &str) -> Vec<Ipv4Net> {
+        parse_cidrs(cidrs).unwrap()
+    }
+
+    fn walk(mut generator: impl TargetGenerator) -> Vec<IpAddr> {
+        std::iter::from_fn(|| generator.next_ip()).collect()
+    }
+
+    fn assert_permutation(spec: &str, expected: usize) {
+        let ips = walk(RandomPermutation::new(&cidrs(spec)));
+        let unique: HashSet<_> = ips.iter().collect();
+
+        assert_eq!(ips.len(), expected, "{}", spec);
+        assert_eq!(unique.len(), expected, "{}", spec);
+
+        let nets = cidrs(spec);
+        for ip in &ips {
+            let IpAddr::V4(ip) = ip else { panic!("{}", ip) };
+            assert!(nets.iter().any(|net| net.contains(ip)), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn permutation_visits_each_address_once() {
+        assert_permutation("10.0.0.1", 1);
+        assert_permutation("10.0.0.0/31", 2);
+        assert_permutation("10.0.0.0/30", 4);
+        assert_permutation("10.0.0.0/24", 256);
+        assert_permutation("10.0.0.0/24,192.168.1.0/28", 272);
+        assert_permutation("10.0.0.0/22", 1024);
+    }
+
+    #[test]
+    fn permutation_merges_overlapping_cidrs() {
+        assert_permutation("10.0.0.0/24,10.0.0.128/25,10.0.0.7", 256);
+        assert_permutation("10.0.0.0/25,10.0.0.128/25,10.0.1.0/24", 512);
+        assert_permutation("10.0.0.0/24,10.0.0.0/24", 256);
+    }
+
+    #[test]
+    fn permutation_of_nothing_is_empty() {
+        assert!(walk(RandomPermutation::new(&[])).is_empty());
+    }
+
+    #[test]
+    fn sequential_walks_merged_ranges_in_order() {
+        let ips = walk(Sequential::new(&cidrs(
+            "10.0.1.0/31,10.0.0.0/30,10.0.0.2/31,10.0.0.4",
+        )));
+        let expected: Vec<IpAddr> = [
+            "10.0.0.0", "10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.1.0", "10.0.1.1",
+        ]
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+
+        assert_eq!(ips, expected);
+        assert!(walk(Sequential::new(&[])).is_empty());
+    }
+
+    #[test]
+    fn sequential_stops_at_the_end_of_ipv4() {
+        let ips = walk(Sequential::new(&cidrs("255.255.255.254/31")));
+
+        assert_eq!(ips.len(), 2);
+        assert_eq!(ips[1], "255.255.255.255".parse::<IpAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn known_hosts_keep_port_and_hostname() {
+        let path = std::env::temp_dir().join(format!("mse-{}-known.sqlite", std::process::id()));
+        let db: Store = std::sync::Arc::new(SqliteStore::open(path.to_str().unwrap()).unwrap());
+
+        for (addr, hostname) in [
+            ("1.2.3.4:25565", None),
+            ("1.2.3.4:25580", None),
+            ("1.2.3.4:25565", Some("mc.example.com")),
+        ] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            let record = serde_json::from_value(serde_json::json!({
+                "ip": addr.ip().to_string(),
+                "port": addr.port(),
+                "hostname": hostname,
+                "status": {}
+            }))
+            .unwrap();
+            db.add(&record).await.unwrap();
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        spawn(from_spec("known@25565", &[25565], &db).unwrap(), tx);
+
+        let mut targets = Vec::new();
+        while let Some(target) = rx.recv().await {
+            targets.push(target.to_string());
+        }
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert_eq!(
+            targets,
+            [
+                "1.2.3.4:25565",
+                "1.2.3.4:25580",
+                "mc.example.com -> 1.2.3.4:25565"
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_adjacent_and_nested_ranges() {
+        assert_eq!(
+            merge_ranges(&cidrs("10.0.0.0/25,10.0.0.128/25,10.0.0.64/26,10.0.2.0/24")),
+            [(0x0A000000, 0x0A000100), (0x0A000200, 0x0A000300)]
+        );
+    }
+
+    #[test]
+    fn parses_ports() {
+        assert_eq!(parse_ports("25565").unwrap(), [25565]);
+        assert_eq!(
+            parse_ports("25567, 25565-25566,25565").unwrap(),
+            [25565, 25566, 25567]
+        );
+        assert_eq!(parse_ports("65534-65535").unwrap(), [65534, 65535]);
+    }
+
+    #[test]
+    fn rejects_bad_ports() {
+        assert!(parse_ports("").is_err());
+        assert!(parse_ports(" , ").is_err());
+        assert!(parse_ports("65536").is_err());
+        assert!(parse_ports("abc").is_err());
+        assert!(parse_ports("25565-").is_err());
+        assert!(parse_ports("-25565").is_err());
+        // Reversed ranges are empty
+        assert!(parse_ports("25600-25565").is_err());
+    }
+
+    #[test]
+    fn miller_rabin_matches_trial_division() {
+        let trial = |n: u64| {
+            n >= 2
+                && (2..n)
+                    .take_while(|d| d * d <= n)
+                    .all(|d| !n.is_multiple_of(d))
+        };
+
+        for n in 0..10_000 {
+            assert_eq!(is_prime(n), trial(n), "{}", n);
+        }
+    }
+
+    #[test]
+    fn miller_rabin_large_numbers() {
+        assert!(is_prime(4_294_967_311));
+        assert!(is_prime(18_446_744_073_709_551_557));
+        // Strong pseudoprimes to small bases
+        assert!(!is_prime(3_215_031_751));
+        assert!(!is_prime(3_825_123_056_546_413_051));
+        assert!(!is_prime(4_294_967_297));
+
+        assert_eq!(next_prime(1 << 32), 4_294_967_311);
+        assert_eq!(next_prime(257), 257);
+        assert_eq!(next_prime(258), 263);
+    }
+
+    #[test]
+    fn primitive_root_generates_the_whole_group() {
+        for prime in [2, 3, 5, 7, 257, 263, 65537] {
+            let g = primitive_root(prime);
+
+            let mut x = 1;
+            let mut order = 0;
+            loop {
+                x = mul_mod(x, g, prime);
+                order += 1;
+                if x == 1 {
+                    break;
+                }
+            }
+
+            assert_eq!(order, prime - 1, "g = {} mod {}", g, prime);
+        }
+    }
+}
@@ -1,119 +1,252 @@
-use std::{env, io::Result, net::SocketAddr, sync::Arc};
+use std::{env, io::Result, net::SocketAddr, pin::pin, process, sync::Arc};
 
 use checker::{get_full_info, get_status};
 use colored::Colorize;
-use database::MongoDBClient;
-use mongodb::bson::{doc, DateTime};
+use database::{get_ips, open_store, IpFilter, Store};
+use exclude::Exclusions;
+use futures_util::StreamExt;
+use model::StatusResponse;
+use ratelimit::RateLimiter;
+use resolver::{resolve, DnsResolver};
+use target::Target;
 use tokio::{
     sync::{
         mpsc::{self, Receiver, Sender},
         Mutex,
     },
     task::JoinSet,
-    time::{sleep, timeout, Duration},
+    time::{sleep, Duration},
 };
-use utils::{check_port_open, get_random_ip, StatusWrap};
+use utils::{check_port_open, get_random_ip};
 
 mod packets;
 
+mod bedrock;
+mod chat;
 mod checker;
 mod database;
+mod exclude;
+mod favicon;
+mod fingerprint;
+mod forge;
+mod generators;
+mod model;
+mod prefilter;
+mod query;
+mod ratelimit;
+mod resolver;
+mod target;
 mod utils;
 
-async fn process_ip(ip: SocketAddr, db: Arc<Mutex<MongoDBClient>>) -> Result<()> {
-    let info = get_full_info(ip).await?;
-    let info_parsed = StatusWrap::from_value(&info);
+async fn process_ip(
+    target: Target,
+    db: Store,
+    query: bool,
+    limiter: Arc<RateLimiter>,
+) -> Result<()> {
+    let record = get_full_info(&target, query, &limiter).await?;
 
-    db.lock().await.add(&info).await?;
+    db.add(&record).await?;
+    db.add_snapshot(&target, &record.status).await?;
 
-    if info_parsed.license != 0 {
+    if record.license != Some(0) {
         return Ok(());
     }
 
     println!(
         "[+] ({}) -> {} | {} | {}/{}",
-        info_parsed.ip,
-        info_parsed.version.red(),
-        info_parsed.description.replace("\n", "|").blue(),
-        info_parsed.online,
-        info_parsed.max_online
+        record.ip,
+        record.status.version.name.red(),
+        record
+            .status
+            .description
+            .component()
+            .ansi()
+            .replace('\n', "|"),
+        record.status.players.online,
+        record.status.players.max
     );
 
     Ok(())
 }
 
-async fn wait_for_ip(mut rx: Receiver<SocketAddr>) {
-    let db = MongoDBClient::new().await;
+/// `status` is the pong the generator already got
+async fn process_bedrock_ip(ip: SocketAddr, status: StatusResponse, db: Store) -> Result<()> {
+    let record = bedrock::get_full_info(ip, status);
 
-    while let Some(ip) = rx.recv().await {
-        tokio::spawn(process_ip(ip, db.clone()));
+    db.add(&record).await?;
+    db.add_snapshot(&Target::from(ip), &record.status).await?;
+
+    println!(
+        "[+] ({}) -> {} | {} | {}/{}",
+        record.ip,
+        format!("Bedrock {}", record.status.version.name).red(),
+        record
+            .status
+            .description
+            .component()
+            .ansi()
+            .replace('\n', "|"),
+        record.status.players.online,
+        record.status.players.max
+    );
+
+    Ok(())
+}
+
+async fn wait_for_ip(mut rx: Receiver<Target>, db: Store, query: bool, limiter: Arc<RateLimiter>) {
+    while let Some(target) = rx.recv().await {
+        tokio::spawn(process_ip(target, db.clone(), query, limiter.clone()));
+    }
+}
+
+async fn wait_for_bedrock_ip(mut rx: Receiver<(SocketAddr, StatusResponse)>, db: Store) {
+    while let Some((ip, status)) = rx.recv().await {
+        tokio::spawn(process_bedrock_ip(ip, status, db.clone()));
     }
 }
 
-async fn generator(tx: Arc<Sender<SocketAddr>>) {
+async fn bedrock_generator(
+    tx: Arc<Sender<(SocketAddr, StatusResponse)>>,
+    exclusions: Arc<Exclusions>,
+    limiter: Arc<RateLimiter>,
+) {
     loop {
-        let ip = get_random_ip();
+        let ip = SocketAddr::new(get_random_ip(), bedrock::DEFAULT_PORT);
+        if exclusions.contains(ip.ip()) {
+            continue;
+        }
 
-        if check_port_open(ip, 25565).await {
-            if let Err(_) = tx.send(SocketAddr::new(ip, 25565)).await {
-                return;
-            }
+        limiter.connect.acquire().await;
+
+        // There is no handshake to pre-filter with over UDP, so probe with the ping itself
+        let Ok(status) = bedrock::get_status(ip).await else {
+            continue;
+        };
+        if tx.send((ip, status)).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn generator(
+    targets: Arc<Mutex<Receiver<Target>>>,
+    tx: Arc<Sender<Target>>,
+    exclusions: Arc<Exclusions>,
+    limiter: Arc<RateLimiter>,
+) {
+    loop {
+        let Some(target) = targets.lock().await.recv().await else {
+            return;
+        };
+        let addr = target.addr;
+
+        if exclusions.contains(addr.ip()) {
+            continue;
+        }
+
+        limiter.connect.acquire().await;
+        let open = {
+            let _permit = limiter.subnets.acquire(addr.ip()).await;
+            check_port_open(addr.ip(), addr.port()).await
+        };
+
+        if open && tx.send(target).await.is_err() {
+            return;
         }
     }
 }
 
-async fn update_ip(ip: SocketAddr, db: Arc<Mutex<MongoDBClient>>) -> Result<()> {
-    let info = get_status(ip).await?;
-
-    db.lock()
-        .await
-        .servers
-        .update_one(
-            doc! {"ip": ip.ip().to_string()},
-            doc! {
-            "$set": {
-                "status": mongodb::bson::to_bson(&info).unwrap(),
-                "lastSeen": DateTime::now()
-            },
-            "$addToSet": {
-                "players": {
-                    "$each": mongodb::bson::to_bson(info["players"]["sample"].as_array().unwrap_or(&vec![])).unwrap()
-                }
+/// One `host` or `host:port` per line, `#` starts a comment
+async fn resolve_hostnames(
+    path: String,
+    tx: Arc<Sender<Target>>,
+    exclusions: Arc<Exclusions>,
+    db: Store,
+) -> Result<()> {
+    let hostnames = tokio::fs::read_to_string(path).await?;
+    let resolver = DnsResolver::new();
+
+    for line in hostnames.lines() {
+        let host = line.split('#').next().unwrap_or("").trim();
+        if host.is_empty() {
+            continue;
+        }
+
+        let resolved = match resolve(&resolver, host).await {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                eprintln!("Failed to resolve {}: {}", host, e);
+                continue;
             }
-            },
-        )
-        .await
-        .unwrap();
+        };
+
+        for resolved in resolved {
+            if exclusions.contains(resolved.target.addr.ip()) {
+                continue;
+            }
+
+            db.add_hostname(&resolved).await?;
+
+            if tx.send(resolved.target).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn update_ip(target: Target, db: Store, limiter: Arc<RateLimiter>) -> Result<()> {
+    limiter.status.acquire().await;
+    let info = {
+        let _permit = limiter.subnets.acquire(target.addr.ip()).await;
+        get_status(&target).await?
+    };
+
+    db.update(&target, &info).await?;
+    db.add_snapshot(&target, &info).await?;
+
+    Ok(())
+}
+
+async fn update_bedrock_ip(ip: SocketAddr, db: Store, limiter: Arc<RateLimiter>) -> Result<()> {
+    limiter.status.acquire().await;
+    let info = bedrock::get_status(ip).await?;
+    let target = Target::from(ip);
+
+    db.update(&target, &info).await?;
+    db.add_snapshot(&target, &info).await?;
 
     Ok(())
 }
 
-async fn update() -> Result<()> {
-    let db = MongoDBClient::new().await;
+/// `UPDATE_MAX_AGE_DAYS` skips servers not seen for that long (0 = re-ping everything)
+fn update_filter(edition: &str) -> IpFilter {
+    let days: i64 = env::var("UPDATE_MAX_AGE_DAYS")
+        .unwrap_or("0".to_string())
+        .parse()
+        .unwrap();
+
+    let mut filter = IpFilter::edition(edition);
+    filter.seen_within_days = Some(days).filter(|days| *days > 0);
+    filter
+}
 
-    let servers = db.lock().await.get_ips().await.unwrap();
+async fn update_bedrock(db: Store, limiter: Arc<RateLimiter>) -> Result<()> {
+    let mut servers = pin!(get_ips(db.clone(), update_filter("bedrock")).chunks(100));
+    let mut count = 0;
 
-    println!("Updating: {} servers", servers.len());
+    println!("Updating: bedrock servers");
 
-    for chunk in servers.chunks(100) {
+    while let Some(chunk) = servers.next().await {
         let mut set = JoinSet::new();
 
         for server in chunk {
-            let db_clone = db.clone();
-            set.spawn(timeout(
-                Duration::from_secs(5),
-                update_ip(
-                    format!(
-                        "{}:{}",
-                        server.0,
-                        server.1
-                    )
-                    .parse()
-                    .unwrap(),
-                    db_clone,
-                ),
-            ));
+            let server = server?;
+            set.spawn(update_bedrock_ip(server.addr, db.clone(), limiter.clone()));
         }
+        count += set.len();
 
         while let Some(result) = set.join_next().await {
             match result {
@@ -123,14 +256,50 @@ async fn update() -> Result<()> {
         }
     }
 
-    println!("Updating: {}", "done".green());
+    println!("Updating bedrock: {} ({} servers)", "done".green(), count);
 
     Ok(())
 }
 
-async fn update_loop() {
+async fn update(db: Store, limiter: Arc<RateLimiter>) -> Result<()> {
+    let mut servers = pin!(get_ips(db.clone(), update_filter("java")).chunks(100));
+    let mut count = 0;
+
+    println!("Updating: java servers");
+
+    while let Some(chunk) = servers.next().await {
+        let mut set = JoinSet::new();
+
+        for server in chunk {
+            let server = server?;
+            set.spawn(update_ip(server, db.clone(), limiter.clone()));
+        }
+        count += set.len();
+
+        while let Some(result) = set.join_next().await {
+            match result {
+                Ok(_) => {}
+                Err(e) => eprintln!("Task failed: {:?}", e),
+            }
+        }
+    }
+
+    println!("Updating: {} ({} servers)", "done".green(), count);
+
+    Ok(())
+}
+
+async fn update_loop(db: Store, limiter: Arc<RateLimiter>) {
     loop {
-        update().await.unwrap();
+        if let Err(e) = update(db.clone(), limiter.clone()).await {
+            eprintln!("Update failed: {}", e);
+        }
+        if let Err(e) = update_bedrock(db.clone(), limiter.clone()).await {
+            eprintln!("Bedrock update failed: {}", e);
+        }
+        if let Err(e) = db.downsample_snapshots().await {
+            eprintln!("Downsampling snapshots failed: {}", e);
+        }
 
         sleep(Duration::from_secs(5 * 60)).await;
     }
@@ -146,19 +315,109 @@ async fn main() {
         .parse()
         .unwrap();
 
-    let update_thread = tokio::spawn(update_loop());
+    let bedrock_threads: i32 = env::var("BEDROCK_THREADS")
+        .unwrap_or("0".to_string())
+        .parse()
+        .unwrap();
+
+    let query: bool = env::var("QUERY")
+        .unwrap_or("true".to_string())
+        .parse()
+        .unwrap();
+
+    let prefilter = env::var("PREFILTER").unwrap_or("off".to_string());
+
+    let prefilter_pps: u32 = env::var("PREFILTER_PPS")
+        .unwrap_or("1000".to_string())
+        .parse()
+        .unwrap();
+
+    let mut exclusions = Exclusions::new();
+    if let Ok(path) = env::var("EXCLUDE_FILE") {
+        let count = exclusions.load_file(&path).unwrap();
+        println!("Excluding: {} ranges from {}", count, path);
+    }
+    let exclusions = Arc::new(exclusions);
+
+    let limiter = Arc::new(RateLimiter::from_env());
+
+    // Nothing works without the store; exit so the container gets restarted
+    let db = match open_store().await {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to open the database: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let update_thread = tokio::spawn(update_loop(db.clone(), limiter.clone()));
 
     let (tx, rx) = mpsc::channel(256);
-    let reciever_thread = tokio::spawn(wait_for_ip(rx));
+    let reciever_thread = tokio::spawn(wait_for_ip(rx, db.clone(), query, limiter.clone()));
 
     let mut generators = Vec::new();
     let tx = Arc::new(tx);
 
-    for _ in 0..threads {
-        generators.push(tokio::spawn(generator(tx.clone())))
+    let (targets_tx, targets_rx) = mpsc::channel(1024);
+    let targets = env::var("TARGETS").unwrap_or("random".to_string());
+    let ports = generators::parse_ports(&env::var("PORTS").unwrap_or("25565".to_string())).unwrap();
+
+    for spec in targets.split(';').filter(|spec| !spec.trim().is_empty()) {
+        let source = generators::from_spec(spec, &ports, &db).unwrap();
+        generators::spawn(source, targets_tx.clone());
+    }
+    drop(targets_tx);
+
+    let targets_rx = Arc::new(Mutex::new(targets_rx));
+
+    if let Ok(path) = env::var("HOSTNAMES_FILE") {
+        let tx = tx.clone();
+        let exclusions = exclusions.clone();
+        let db = db.clone();
+        tokio::spawn(async move {
+            if let Err(e) = resolve_hostnames(path, tx, exclusions, db).await {
+                eprintln!("Hostnames: {}", e);
+            }
+        });
+    }
+
+    match prefilter.as_str() {
+        "syn" | "connect" => generators.push(tokio::spawn(prefilter::run(
+            targets_rx.clone(),
+            tx.clone(),
+            exclusions.clone(),
+            limiter.clone(),
+            prefilter == "syn",
+            prefilter_pps,
+            threads as usize,
+        ))),
+        _ => {
+            for _ in 0..threads {
+                generators.push(tokio::spawn(generator(
+                    targets_rx.clone(),
+                    tx.clone(),
+                    exclusions.clone(),
+                    limiter.clone(),
+                )))
+            }
+        }
+    }
+
+    let (bedrock_tx, bedrock_rx) = mpsc::channel(256);
+    let bedrock_reciever_thread = tokio::spawn(wait_for_bedrock_ip(bedrock_rx, db.clone()));
+
+    let bedrock_tx = Arc::new(bedrock_tx);
+
+    for _ in 0..bedrock_threads {
+        generators.push(tokio::spawn(bedrock_generator(
+            bedrock_tx.clone(),
+            exclusions.clone(),
+            limiter.clone(),
+        )))
     }
 
     reciever_thread.await.unwrap();
+    bedrock_reciever_thread.await.unwrap();
     update_thread.await.unwrap();
 
     for generator in generators {
@@ -0,0 +1,441 @@
+use std::fmt;
+
+use mongodb::bson::DateTime;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use crate::chat::Component;
+
+/// One scanned server as stored in the `servers` collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerRecord {
+    pub ip: String,
+    /// Older records stored the port as a string
+    #[serde(deserialize_with = "port")]
+    pub port: u16,
+    /// Backfilled on startup for records that predate it, see `MongoDBClient`
+    #[serde(default)]
+    pub address: String,
+    /// Set when the record came from a hostname, see `Target`
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Records written before the edition field existed are Java servers
+    #[serde(default = "java")]
+    pub edition: String,
+    /// 1 online mode, 0 offline mode, -1 unknown. Not probed for Bedrock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<i64>,
+    /// What the login probe ran into, `license` is derived from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthMode>,
+    /// Plain text of the Disconnect packet the login probe got. Written as
+    /// null so a rescan clears an old kick message.
+    #[serde(default)]
+    pub disconnect_reason: Option<String>,
+    /// SHA-256 of the key from the Encryption Request, online mode only. The
+    /// same key on several records means one backend behind several addresses.
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Every key seen and when. Vanilla makes a new key pair on each start,
+    /// so this shows restarts and key rotation over time.
+    #[serde(default)]
+    pub public_keys: Vec<PublicKey>,
+    /// Login Plugin Request channels, e.g. `velocity:player_info`. Null like
+    /// `disconnect_reason`.
+    #[serde(default)]
+    pub login_channels: Option<Vec<String>>,
+    /// Set by the store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime>,
+    /// Every player ever seen in the sample
+    #[serde(default)]
+    pub players: Vec<Player>,
+    /// SHA-256 of the icon in the favicons collection. The data URI itself is
+    /// stripped from `status` before storing.
+    #[serde(default)]
+    pub favicon: Option<String>,
+    /// Plain text of `status.description`, for searching
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mods: Option<Vec<Value>>,
+    pub status: StatusResponse,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<Value>,
+    /// Best guess at what the server runs, see `fingerprint`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub software: Option<Software>,
+}
+
+/// A key fingerprint from the Encryption Request. Records from before the
+/// timestamps were kept hold bare fingerprints, those read without times.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKey {
+    pub fingerprint: String,
+    pub first_seen: Option<DateTime>,
+    pub last_seen: Option<DateTime>,
+}
+
+impl PublicKey {
+    /// Seen for the first time just now
+    pub fn new(fingerprint: String) -> Self {
+        let now = DateTime::now();
+
+        PublicKey {
+            fingerprint,
+            first_seen: Some(now),
+            last_seen: Some(now),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Fingerprint(String),
+            #[serde(rename_all = "camelCase")]
+            Key {
+                fingerprint: String,
+                #[serde(default)]
+                first_seen: Option<DateTime>,
+                #[serde(default)]
+                last_seen: Option<DateTime>,
+            },
+        }
+
+        Ok(match Stored::deserialize(deserializer)? {
+            Stored::Fingerprint(fingerprint) => PublicKey {
+                fingerprint,
+                first_seen: None,
+                last_seen: None,
+            },
+            Stored::Key {
+                fingerprint,
+                first_seen,
+                last_seen,
+            } => PublicKey {
+                fingerprint,
+                first_seen,
+                last_seen,
+            },
+        })
+    }
+}
+
+/// Outcome of the login probe, stored as `{"mode": "version-mismatch", "expected": "1.20.4"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum AuthMode {
+    /// Encryption Request
+    Online,
+    /// Login Success without encryption
+    Offline,
+    Whitelisted,
+    /// `expected` is the version the kick message asked for
+    VersionMismatch {
+        expected: Option<String>,
+    },
+    /// BungeeCord IP forwarding, BungeeGuard or Velocity modern forwarding
+    /// backend reached directly
+    ProxyForwarding,
+    Banned,
+    /// Connection throttle, we came back too soon
+    Throttled,
+    /// Server full
+    Full,
+    /// Connection closed after we declined a Login Plugin Request
+    LoginPlugin,
+    /// Any other Disconnect
+    Disconnected,
+}
+
+impl AuthMode {
+    /// Value for `ServerRecord::license`
+    pub fn license(&self) -> i64 {
+        match self {
+            AuthMode::Online => 1,
+            AuthMode::Offline => 0,
+            _ => -1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Software {
+    /// `paper`, `velocity`, `forge`, ... or `unknown`
+    pub name: String,
+    /// 0.0 - 1.0
+    pub confidence: f64,
+    /// Signals that pointed at `name`
+    #[serde(default)]
+    pub evidence: Vec<String>,
+}
+
+/// https://wiki.vg/Server_List_Ping#Status_Response
+///
+/// Everything not modelled here (`favicon`, `modinfo`, `forgeData`,
+/// `enforcesSecureChat`, Bedrock's extra MOTD fields, ...) is kept in `extra`
+/// so the response is stored as the server sent it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatusResponse {
+    #[serde(default)]
+    pub version: Version,
+    #[serde(default)]
+    pub players: Players,
+    #[serde(default)]
+    pub description: Description,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+    /// Top-level keys in the order the server sent them. Only used for
+    /// fingerprinting, never stored.
+    #[serde(skip)]
+    pub key_order: Vec<String>,
+}
+
+impl StatusResponse {
+    /// Parses a status JSON while keeping track of the key order, which
+    /// `Map` loses
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let mut status: StatusResponse = serde_json::from_str(json)?;
+        status.key_order = serde_json::from_str::<KeyOrder>(json)?.0;
+        Ok(status)
+    }
+}
+
+struct KeyOrder(Vec<String>);
+
+impl<'de> Deserialize<'de> for KeyOrder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyOrderVisitor;
+
+        impl<'de> de::Visitor<'de> for KeyOrderVisitor {
+            type Value = KeyOrder;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<KeyOrder, A::Error> {
+                let mut keys = Vec::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    map.next_value::<de::IgnoredAny>()?;
+                    keys.push(key);
+                }
+                Ok(KeyOrder(keys))
+            }
+        }
+
+        deserializer.deserialize_map(KeyOrderVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    #[serde(default)]
+    pub name: String,
+    /// -1 when the server didn't tell
+    #[serde(default = "unknown", deserialize_with = "lenient")]
+    pub protocol: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Players {
+    #[serde(default = "unknown", deserialize_with = "lenient")]
+    pub max: i64,
+    #[serde(default = "unknown", deserialize_with = "lenient")]
+    pub online: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<Vec<Player>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub id: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A plain string or a chat component, kept as sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Description {
+    Text(String),
+    Component(Value),
+}
+
+impl Description {
+    pub fn component(&self) -> Component {
+        match self {
+            Description::Text(text) => Component::parse_legacy(text),
+            Description::Component(component) => Component::parse(component),
+        }
+    }
+
+    /// Text without formatting or legacy codes
+    pub fn plain(&self) -> String {
+        self.component().plain()
+    }
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Version {
+            name: String::new(),
+            protocol: -1,
+            extra: Map::new(),
+        }
+    }
+}
+
+impl Default for Players {
+    fn default() -> Self {
+        Players {
+            max: -1,
+            online: -1,
+            sample: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+impl Default for Description {
+    fn default() -> Self {
+        Description::Text(String::new())
+    }
+}
+
+fn java() -> String {
+    "java".to_string()
+}
+
+fn unknown() -> i64 {
+    -1
+}
+
+/// Numbers as sent by odd servers: floats, numeric strings, anything else is -1
+/// rather than failing the whole status
+fn lenient<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_i64().or(n.as_f64().map(|f| f as i64)).unwrap_or(-1),
+        Value::String(s) => s.trim().parse().unwrap_or(-1),
+        _ => -1,
+    })
+}
+
+/// Accepts both the numeric port and the string written by older versions
+fn port<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    struct PortVisitor;
+
+    impl de::Visitor<'_> for PortVisitor {
+        type Value = u16;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a port number")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<u16, E> {
+            u16::try_from(v).map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<u16, E> {
+            u16::try_from(v).map_err(E::custom)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<u16, E> {
+            v.parse().map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_any(PortVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown_version_fields_and_key_order() {
+        let status = StatusResponse::from_json(
+            r#"{"version":{"name":"Paper 1.20.4","protocol":765,"brand":"x"},"players":{"max":20,"online":1},"description":"hi"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(status.version.extra["brand"], "x");
+        assert_eq!(status.key_order, ["version", "players", "description"]);
+    }
+
+    #[test]
+    fn odd_number_types_become_unknown() {
+        let status = StatusResponse::from_json(
+            r#"{"version":{"name":"1.8","protocol":"47"},"players":{"max":20.0,"online":{}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(status.version.protocol, 47);
+        assert_eq!(status.players.max, 20);
+        assert_eq!(status.players.online, -1);
+    }
+
+    #[test]
+    fn public_keys_round_trip() {
+        let key = PublicKey::new("ab12".to_string());
+
+        let json = serde_json::to_value(&key).unwrap();
+        assert_eq!(serde_json::from_value::<PublicKey>(json).unwrap(), key);
+
+        let bson = mongodb::bson::to_document(&key).unwrap();
+        assert!(bson.get_datetime("firstSeen").is_ok());
+        assert_eq!(
+            mongodb::bson::from_document::<PublicKey>(bson).unwrap(),
+            key
+        );
+    }
+
+    #[test]
+    fn reads_bare_fingerprints() {
+        let keys: Vec<PublicKey> = serde_json::from_value(serde_json::json!([
+            "ab12",
+            {"fingerprint": "cd34"}
+        ]))
+        .unwrap();
+
+        assert_eq!(keys[0].fingerprint, "ab12");
+        assert_eq!(keys[0].first_seen, None);
+        assert_eq!(keys[1].fingerprint, "cd34");
+        assert_eq!(keys[1].last_seen, None);
+
+        let document = mongodb::bson::doc! {"keys": ["ab12"]};
+        let keys: Vec<PublicKey> =
+            mongodb::bson::from_bson(document.get("keys").unwrap().clone()).unwrap();
+        assert_eq!(keys[0].fingerprint, "ab12");
+    }
+
+    #[test]
+    fn reads_records_from_before_address() {
+        let record: ServerRecord = serde_json::from_value(serde_json::json!({
+            "ip": "1.2.3.4",
+            "port": "25565",
+            "status": {"version": {"name": "1.8", "protocol": 47}}
+        }))
+        .unwrap();
+
+        assert_eq!(record.port, 25565);
+        assert_eq!(record.address, "");
+        assert_eq!(record.edition, "java");
+    }
+}
@@ -1 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod packets;
+pub mod registry;
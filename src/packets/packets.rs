@@ -4,6 +4,11 @@ use minecraft_protocol::{
     packet_builder::PacketBuilder, packet_reader::PacketReader, types::var_int::VarInt,
     UncompressedPacket,
 };
+use sha2::{Digest, Sha256};
+
+use crate::chat::Component;
+
+use super::registry::LoginStartLayout;
 
 pub trait PacketActions: Sized {
     fn serialize(self) -> UncompressedPacket;
@@ -20,12 +25,27 @@ pub struct Handshake {
     pub next_state: VarInt,
 }
 
-/// PacketID 0x00
+/// PacketID 0x00. Which fields follow the name depends on the protocol
+/// version, see `LoginStartLayout`.
 #[derive(Clone)]
 pub struct LoginStart {
     pub packet_id: VarInt,
     pub name: String,
     pub uuid: u128,
+    pub layout: LoginStartLayout,
+}
+
+/// PacketID 0x01, clientbound. 1.7 used short length prefixes and fails to
+/// parse, `login` still counts it as online mode. `should_authenticate` was
+/// added in 1.20.5.
+#[derive(Clone, Debug)]
+pub struct EncryptionRequest {
+    pub packet_id: VarInt,
+    pub server_id: String,
+    /// DER-encoded RSA public key
+    pub public_key: Vec<u8>,
+    pub verify_token: Vec<u8>,
+    pub should_authenticate: Option<bool>,
 }
 
 /// PacketID 0x03
@@ -35,6 +55,32 @@ pub struct SetCompression {
     pub threshold: VarInt,
 }
 
+/// PacketID 0x00, clientbound in login. Login keeps sending the reason as
+/// JSON text even after play switched to NBT.
+#[derive(Clone, Debug)]
+pub struct Disconnect {
+    pub packet_id: VarInt,
+    pub reason: String,
+}
+
+/// PacketID 0x04, clientbound
+#[derive(Clone, Debug)]
+pub struct LoginPluginRequest {
+    pub packet_id: VarInt,
+    pub message_id: VarInt,
+    pub channel: String,
+    pub data: Vec<u8>,
+}
+
+/// PacketID 0x02, serverbound. `data` is only sent when `successful`.
+#[derive(Clone, Debug)]
+pub struct LoginPluginResponse {
+    pub packet_id: VarInt,
+    pub message_id: VarInt,
+    pub successful: bool,
+    pub data: Vec<u8>,
+}
+
 /// PacketID 0x00
 pub struct Status {
     pub packet_id: VarInt,
@@ -70,22 +116,96 @@ impl PacketActions for Handshake {
 
 impl PacketActions for LoginStart {
     fn serialize(self) -> UncompressedPacket {
-        PacketBuilder::new(self.packet_id)
-            .write_string(self.name)
-            .write_int(self.uuid)
-            .build()
+        let builder = PacketBuilder::new(self.packet_id).write_string(self.name);
+
+        match self.layout {
+            LoginStartLayout::Name => builder,
+            LoginStartLayout::Signature => builder.write_bool(false),
+            LoginStartLayout::SignatureUuid => builder
+                .write_bool(false)
+                .write_bool(true)
+                .write_int(self.uuid),
+            LoginStartLayout::OptionalUuid => builder.write_bool(true).write_int(self.uuid),
+            LoginStartLayout::Uuid => builder.write_int(self.uuid),
+        }
+        .build()
     }
 
+    /// The layout is guessed from what follows the name, signature data is
+    /// not supported
     async fn deserialize(packet: &UncompressedPacket) -> io::Result<Self> {
         let mut packet_reader = PacketReader::new(packet);
 
         let name = packet_reader.read_string().await?;
-        let uuid: u128 = packet_reader.read_int()?;
+        let rest = packet_reader.read_remaining()?;
+        let mut packet_reader = PacketReader::new(&UncompressedPacket {
+            packet_id: packet.packet_id.clone(),
+            data: rest.clone(),
+        });
+
+        let (layout, uuid) = match rest.len() {
+            0 => (LoginStartLayout::Name, 0),
+            1 => (LoginStartLayout::Signature, 0),
+            16 => (LoginStartLayout::Uuid, packet_reader.read_int()?),
+            17 => {
+                packet_reader.read_bool()?;
+                (LoginStartLayout::OptionalUuid, packet_reader.read_int()?)
+            }
+            18 => {
+                packet_reader.read_bool()?;
+                packet_reader.read_bool()?;
+                (LoginStartLayout::SignatureUuid, packet_reader.read_int()?)
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown Login Start layout",
+                ))
+            }
+        };
 
         Ok(LoginStart {
             packet_id: packet.packet_id.clone(),
             name,
             uuid,
+            layout,
+        })
+    }
+}
+
+impl EncryptionRequest {
+    /// SHA-256 of the public key, hex
+    pub fn key_fingerprint(&self) -> String {
+        Sha256::digest(&self.public_key)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+impl PacketActions for EncryptionRequest {
+    fn serialize(self) -> UncompressedPacket {
+        let builder = PacketBuilder::new(self.packet_id)
+            .write_string(self.server_id)
+            .write_byte_array(&self.public_key)
+            .write_byte_array(&self.verify_token);
+
+        match self.should_authenticate {
+            Some(should_authenticate) => builder.write_bool(should_authenticate).build(),
+            None => builder.build(),
+        }
+    }
+
+    async fn deserialize(packet: &UncompressedPacket) -> io::Result<Self> {
+        let mut packet_reader = PacketReader::new(packet);
+
+        Ok(EncryptionRequest {
+            packet_id: packet.packet_id.clone(),
+            server_id: packet_reader.read_string().await?,
+            public_key: packet_reader.read_byte_array().await?,
+            verify_token: packet_reader.read_byte_array().await?,
+            // Older versions end after the verify token
+            should_authenticate: packet_reader.read_bool().ok(),
         })
     }
 }
@@ -123,3 +243,237 @@ impl PacketActions for Status {
         Ok(Status { packet_id, status })
     }
 }
+
+impl Disconnect {
+    /// Some servers send plain or `§`-formatted text instead of JSON
+    pub fn component(&self) -> Component {
+        match serde_json::from_str(&self.reason) {
+            Ok(reason) => Component::parse(&reason),
+            Err(_) => Component::parse_legacy(&self.reason),
+        }
+    }
+}
+
+impl PacketActions for Disconnect {
+    fn serialize(self) -> UncompressedPacket {
+        PacketBuilder::new(self.packet_id)
+            .write_string(self.reason)
+            .build()
+    }
+
+    async fn deserialize(packet: &UncompressedPacket) -> io::Result<Self> {
+        let mut packet_reader = PacketReader::new(packet);
+
+        Ok(Disconnect {
+            packet_id: packet.packet_id.clone(),
+            reason: packet_reader.read_string().await?,
+        })
+    }
+}
+
+impl PacketActions for LoginPluginRequest {
+    fn serialize(self) -> UncompressedPacket {
+        PacketBuilder::new(self.packet_id)
+            .write_var_int(self.message_id)
+            .write_string(self.channel)
+            .write_buffer(&self.data)
+            .build()
+    }
+
+    async fn deserialize(packet: &UncompressedPacket) -> io::Result<Self> {
+        let mut packet_reader = PacketReader::new(packet);
+
+        Ok(LoginPluginRequest {
+            packet_id: packet.packet_id.clone(),
+            message_id: packet_reader.read_var_int().await?,
+            channel: packet_reader.read_string().await?,
+            data: packet_reader.read_remaining()?,
+        })
+    }
+}
+
+impl PacketActions for LoginPluginResponse {
+    fn serialize(self) -> UncompressedPacket {
+        let builder = PacketBuilder::new(self.packet_id)
+            .write_var_int(self.message_id)
+            .write_bool(self.successful);
+
+        match self.successful {
+            true => builder.write_buffer(&self.data).build(),
+            false => builder.build(),
+        }
+    }
+
+    async fn deserialize(packet: &UncompressedPacket) -> io::Result<Self> {
+        let mut packet_reader = PacketReader::new(packet);
+
+        Ok(LoginPluginResponse {
+            packet_id: packet.packet_id.clone(),
+            message_id: packet_reader.read_var_int().await?,
+            successful: packet_reader.read_bool()?,
+            data: packet_reader.read_remaining()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn round_trip(reason: &str) -> Disconnect {
+        let packet = Disconnect {
+            packet_id: VarInt(0x00),
+            reason: reason.to_string(),
+        }
+        .serialize();
+
+        Disconnect::deserialize(&packet).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn disconnect_round_trip() {
+        let reason = r#"{"text":"You are not white-listed on this server!"}"#;
+        let disconnect = round_trip(reason).await;
+
+        assert_eq!(disconnect.packet_id.0, 0x00);
+        assert_eq!(disconnect.reason, reason);
+        assert_eq!(
+            disconnect.component().plain(),
+            "You are not white-listed on this server!"
+        );
+    }
+
+    #[tokio::test]
+    async fn disconnect_translate_reason() {
+        let disconnect =
+            round_trip(r#"{"translate":"multiplayer.disconnect.incompatible","with":["1.21.1"]}"#)
+                .await;
+
+        assert_eq!(
+            disconnect.component().plain(),
+            "Incompatible client! Please use 1.21.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn disconnect_plain_and_legacy_text() {
+        let disconnect = round_trip("Server is restarting").await;
+        assert_eq!(
+            disconnect.component(),
+            Component::text("Server is restarting")
+        );
+
+        let disconnect = round_trip("\u{a7}cYou are \u{a7}lbanned\u{a7}r!").await;
+        assert_eq!(disconnect.component().plain(), "You are banned!");
+
+        // Broken JSON is shown as it came
+        let disconnect = round_trip(r#"{"text":"unterminated"#).await;
+        assert_eq!(disconnect.component().plain(), r#"{"text":"unterminated"#);
+
+        // A bare JSON string is valid JSON too
+        let disconnect = round_trip(r#""\u00a7aquoted""#).await;
+        assert_eq!(disconnect.component().plain(), "quoted");
+    }
+
+    #[tokio::test]
+    async fn disconnect_truncated() {
+        let mut packet = Disconnect {
+            packet_id: VarInt(0x00),
+            reason: "Server is restarting".to_string(),
+        }
+        .serialize();
+        packet.data.truncate(5);
+
+        assert!(Disconnect::deserialize(&packet).await.is_err());
+    }
+
+    #[test]
+    fn login_start_layouts() {
+        let uuid: u128 = 0x0123_4567_89ab_cdef_0123_4567_89ab_cdef;
+        let name = [3, b'B', b'o', b't'];
+        let cases: [(i64, &[u8]); 9] = [
+            (47, &[]),
+            (758, &[]),
+            (759, &[0]),
+            (760, &[0, 1]),
+            (761, &[1]),
+            (762, &[1]),
+            (763, &[1]),
+            (764, &[]),
+            (767, &[]),
+        ];
+
+        for (protocol, flags) in cases {
+            let layout = LoginStartLayout::for_protocol(protocol);
+            let packet = LoginStart {
+                packet_id: VarInt(0x00),
+                name: "Bot".to_string(),
+                uuid,
+                layout,
+            }
+            .serialize();
+
+            let mut expected = name.to_vec();
+            expected.extend_from_slice(flags);
+            // Everything from 1.19.2 sends the UUID
+            if protocol >= 760 {
+                expected.extend_from_slice(&uuid.to_be_bytes());
+            }
+            assert_eq!(packet.data, expected, "protocol {}", protocol);
+        }
+    }
+
+    fn encryption_request(should_authenticate: Option<bool>) -> UncompressedPacket {
+        EncryptionRequest {
+            packet_id: VarInt(0x01),
+            server_id: String::new(),
+            public_key: vec![0x30, 0x81, 0x9f, 0x30, 0x0d],
+            verify_token: vec![1, 2, 3, 4],
+            should_authenticate,
+        }
+        .serialize()
+    }
+
+    #[tokio::test]
+    async fn encryption_request_fields() {
+        for should_authenticate in [Some(true), Some(false), None] {
+            let packet = encryption_request(should_authenticate);
+            let request = EncryptionRequest::deserialize(&packet).await.unwrap();
+
+            assert_eq!(request.server_id, "");
+            assert_eq!(request.public_key, [0x30, 0x81, 0x9f, 0x30, 0x0d]);
+            assert_eq!(request.verify_token, [1, 2, 3, 4]);
+            assert_eq!(request.should_authenticate, should_authenticate);
+        }
+    }
+
+    #[tokio::test]
+    async fn encryption_request_bad_length() {
+        let mut packet = encryption_request(None);
+        // Key length says 5 bytes, only 2 follow before the packet ends
+        packet.data.truncate(4);
+        assert!(EncryptionRequest::deserialize(&packet).await.is_err());
+
+        let packet = PacketBuilder::new(VarInt(0x01))
+            .write_string(String::new())
+            .write_var_int(VarInt(-1))
+            .build();
+        assert!(EncryptionRequest::deserialize(&packet).await.is_err());
+    }
+
+    /// 1.7 prefixes the arrays with a short instead of a VarInt
+    #[tokio::test]
+    async fn encryption_request_short_prefix() {
+        let mut key = vec![0x30, 0x81, 0x9f];
+        key.resize(162, 0x01);
+        let packet = PacketBuilder::new(VarInt(0x01))
+            .write_string(String::new())
+            .write_buffer(&162i16.to_be_bytes())
+            .write_buffer(&key)
+            .write_buffer(&4i16.to_be_bytes())
+            .write_buffer(&[1, 2, 3, 4])
+            .build();
+
+        assert!(EncryptionRequest::deserialize(&packet).await.is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::ops::RangeInclusive;
+
+use minecraft_protocol::types::var_int::VarInt;
+
+/// https://wiki.vg/Protocol_version_numbers
+///
+/// Only the packets the scanner speaks: handshake, status and login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Handshake,
+    Status,
+    Login,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Serverbound,
+    Clientbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketKind {
+    Handshake,
+    StatusRequest,
+    StatusResponse,
+    LoginStart,
+    LoginPluginResponse,
+    Disconnect,
+    EncryptionRequest,
+    LoginSuccess,
+    SetCompression,
+    LoginPluginRequest,
+}
+
+const ALL: RangeInclusive<i64> = 0..=i64::MAX;
+
+/// (packet, state, direction, protocol versions, id)
+#[rustfmt::skip]
+const PACKETS: [(PacketKind, State, Direction, RangeInclusive<i64>, i32); 10] = [
+    (PacketKind::Handshake, State::Handshake, Direction::Serverbound, ALL, 0x00),
+    (PacketKind::StatusRequest, State::Status, Direction::Serverbound, ALL, 0x00),
+    (PacketKind::StatusResponse, State::Status, Direction::Clientbound, ALL, 0x00),
+    (PacketKind::LoginStart, State::Login, Direction::Serverbound, ALL, 0x00),
+    // 1.13
+    (PacketKind::LoginPluginResponse, State::Login, Direction::Serverbound, 393..=i64::MAX, 0x02),
+    (PacketKind::Disconnect, State::Login, Direction::Clientbound, ALL, 0x00),
+    (PacketKind::EncryptionRequest, State::Login, Direction::Clientbound, ALL, 0x01),
+    (PacketKind::LoginSuccess, State::Login, Direction::Clientbound, ALL, 0x02),
+    // 1.8
+    (PacketKind::SetCompression, State::Login, Direction::Clientbound, 47..=i64::MAX, 0x03),
+    // 1.13
+    (PacketKind::LoginPluginRequest, State::Login, Direction::Clientbound, 393..=i64::MAX, 0x04),
+];
+
+/// Id of `kind` in `protocol`, None if the packet doesn't exist there
+pub fn packet_id(kind: PacketKind, protocol: i64) -> Option<VarInt> {
+    PACKETS
+        .iter()
+        .find(|(k, _, _, versions, _)| *k == kind && versions.contains(&protocol))
+        .map(|(_, _, _, _, id)| VarInt(*id))
+}
+
+/// Which packet `id` is in `protocol`
+pub fn packet_kind(
+    state: State,
+    direction: Direction,
+    protocol: i64,
+    id: i32,
+) -> Option<PacketKind> {
+    PACKETS
+        .iter()
+        .find(|(_, s, d, versions, i)| {
+            *s == state && *d == direction && versions.contains(&protocol) && *i == id
+        })
+        .map(|(kind, _, _, _, _)| *kind)
+}
+
+/// Fields of Login Start after the name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStartLayout {
+    /// Up to 1.18.2
+    Name,
+    /// 1.19: has signature data (false)
+    Signature,
+    /// 1.19.2: has signature data (false), has UUID, UUID
+    SignatureUuid,
+    /// 1.19.3 - 1.20.1: has UUID, UUID
+    OptionalUuid,
+    /// 1.20.2+: UUID
+    Uuid,
+}
+
+impl LoginStartLayout {
+    pub fn for_protocol(protocol: i64) -> Self {
+        match protocol {
+            ..=758 => LoginStartLayout::Name,
+            759 => LoginStartLayout::Signature,
+            760 => LoginStartLayout::SignatureUuid,
+            761..=763 => LoginStartLayout::OptionalUuid,
+            _ => LoginStartLayout::Uuid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(kind: PacketKind, protocol: i64) -> Option<i32> {
+        packet_id(kind, protocol).map(|id| id.0)
+    }
+
+    fn login_kind(protocol: i64, id: i32) -> Option<PacketKind> {
+        packet_kind(State::Login, Direction::Clientbound, protocol, id)
+    }
+
+    #[test]
+    fn set_compression_from_1_8() {
+        assert_eq!(id(PacketKind::SetCompression, 46), None);
+        assert_eq!(id(PacketKind::SetCompression, 47), Some(0x03));
+
+        assert_eq!(login_kind(46, 0x03), None);
+        assert_eq!(login_kind(47, 0x03), Some(PacketKind::SetCompression));
+    }
+
+    #[test]
+    fn login_plugin_from_1_13() {
+        assert_eq!(id(PacketKind::LoginPluginResponse, 392), None);
+        assert_eq!(id(PacketKind::LoginPluginResponse, 393), Some(0x02));
+
+        assert_eq!(login_kind(392, 0x04), None);
+        assert_eq!(login_kind(393, 0x04), Some(PacketKind::LoginPluginRequest));
+    }
+
+    #[test]
+    fn ids_depend_on_state_and_direction() {
+        assert_eq!(login_kind(47, 0x00), Some(PacketKind::Disconnect));
+        assert_eq!(login_kind(47, 0x01), Some(PacketKind::EncryptionRequest));
+        assert_eq!(login_kind(47, 0x02), Some(PacketKind::LoginSuccess));
+        assert_eq!(
+            packet_kind(State::Login, Direction::Serverbound, 765, 0x00),
+            Some(PacketKind::LoginStart)
+        );
+        assert_eq!(
+            packet_kind(State::Status, Direction::Clientbound, 765, 0x00),
+            Some(PacketKind::StatusResponse)
+        );
+        assert_eq!(login_kind(765, 0x05), None);
+    }
+}
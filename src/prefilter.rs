@@ -0,0 +1,281 @@
+use std::{
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex, Semaphore,
+    },
+    time::{interval, Interval, MissedTickBehavior},
+};
+
+use crate::{exclude::Exclusions, ratelimit::RateLimiter, target::Target, utils::check_port_open};
+
+/// TCP flags
+const SYN: u8 = 0x02;
+const ACK: u8 = 0x10;
+
+/// High-throughput alternative to the per-task `check_port_open` generators.
+///
+/// Sends raw SYNs and listens for SYN-ACKs when the process may open raw sockets,
+/// otherwise runs a bounded pool of non-blocking connects. Either way open ports
+/// are sent to `tx` like the generators do.
+pub async fn run(
+    targets: Arc<Mutex<Receiver<Target>>>,
+    tx: Arc<Sender<Target>>,
+    exclusions: Arc<Exclusions>,
+    limiter: Arc<RateLimiter>,
+    syn: bool,
+    pps: u32,
+    concurrency: usize,
+) {
+    let mut ticker = interval(Duration::from_secs_f64(1.0 / pps.max(1) as f64));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+
+    if syn {
+        match SynScanner::new() {
+            Ok(scanner) => {
+                println!("Prefilter: raw SYN at {} pps", pps);
+                return scanner.run(targets, tx, exclusions, limiter, ticker).await;
+            }
+            Err(e) => eprintln!("Prefilter: raw sockets unavailable ({}), using connect", e),
+        }
+    }
+
+    println!(
+        "Prefilter: {} concurrent connects at {} pps",
+        concurrency, pps
+    );
+    connect_pool(targets, tx, exclusions, limiter, ticker, concurrency).await
+}
+
+async fn connect_pool(
+    targets: Arc<Mutex<Receiver<Target>>>,
+    tx: Arc<Sender<Target>>,
+    exclusions: Arc<Exclusions>,
+    limiter: Arc<RateLimiter>,
+    mut ticker: Interval,
+    concurrency: usize,
+) {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+
+    loop {
+        let Some(target) = targets.lock().await.recv().await else {
+            return;
+        };
+        let addr = target.addr;
+        if exclusions.contains(addr.ip()) {
+            continue;
+        }
+
+        ticker.tick().await;
+        limiter.connect.acquire().await;
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let tx = tx.clone();
+        let limiter = limiter.clone();
+
+        tokio::spawn(async move {
+            let _subnet = limiter.subnets.acquire(addr.ip()).await;
+            if check_port_open(addr.ip(), addr.port()).await {
+                let _ = tx.send(target).await;
+            }
+            drop(permit);
+        });
+    }
+}
+
+struct SynScanner {
+    socket: Arc<Socket>,
+    source_ip: Ipv4Addr,
+    source_port: u16,
+    secret: u32,
+}
+
+impl SynScanner {
+    /// Needs CAP_NET_RAW. IPv4 only, v6 and hostname targets go through `check_port_open`.
+    fn new() -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP))?;
+
+        Ok(SynScanner {
+            socket: Arc::new(socket),
+            source_ip: local_ipv4()?,
+            source_port: rand::random::<u16>() % 16384 + 49152,
+            secret: rand::random(),
+        })
+    }
+
+    async fn run(
+        self,
+        targets: Arc<Mutex<Receiver<Target>>>,
+        tx: Arc<Sender<Target>>,
+        exclusions: Arc<Exclusions>,
+        limiter: Arc<RateLimiter>,
+        mut ticker: Interval,
+    ) {
+        let listener = self.socket.clone();
+        let listener_tx = tx.clone();
+        let (source_port, secret) = (self.source_port, self.secret);
+
+        tokio::task::spawn_blocking(move || {
+            listen(&listener, &listener_tx, source_port, secret);
+        });
+
+        // The raw socket is blocking for the listener, so sends get a thread too
+        // instead of stalling the runtime when the send buffer is full
+        let sender = self.socket.clone();
+        let (segments, mut outgoing) = mpsc::channel::<(Vec<u8>, SockAddr)>(1024);
+        tokio::task::spawn_blocking(move || {
+            while let Some((segment, addr)) = outgoing.blocking_recv() {
+                if let Err(e) = sender.send_to(&segment, &addr) {
+                    eprintln!("Prefilter: send failed: {}", e);
+                }
+            }
+        });
+
+        loop {
+            let Some(target) = targets.lock().await.recv().await else {
+                return;
+            };
+            let addr = target.addr;
+            if exclusions.contains(addr.ip()) {
+                continue;
+            }
+
+            ticker.tick().await;
+            limiter.connect.acquire().await;
+
+            match (addr.ip(), &target.hostname) {
+                (IpAddr::V4(ip), None) => {
+                    let segment = syn_segment(
+                        self.source_ip,
+                        ip,
+                        self.source_port,
+                        addr.port(),
+                        cookie(secret, ip, addr.port()),
+                    );
+                    if segments.send((segment, addr.into())).await.is_err() {
+                        return;
+                    }
+                }
+                // A SYN-ACK only tells the address, so targets with a hostname connect
+                _ => {
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        if check_port_open(addr.ip(), addr.port()).await {
+                            let _ = tx.send(target).await;
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Reads IPv4 + TCP from the raw socket and reports SYN-ACKs acknowledging one of our cookies.
+/// The kernel answers those with a RST on its own, as it has no socket for them.
+fn listen(socket: &Socket, tx: &Sender<Target>, source_port: u16, secret: u32) {
+    let mut buf = [0u8; 1500];
+
+    loop {
+        let len = match (&*socket).read(&mut buf) {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            // Don't spin on a socket that keeps failing
+            Err(e) => {
+                eprintln!("Prefilter: receive failed: {}", e);
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+        let packet = &buf[..len];
+
+        if packet.len() < 20 {
+            continue;
+        }
+        let ihl = ((packet[0] & 0x0F) as usize) * 4;
+        let Some(tcp) = packet.get(ihl..ihl + 20) else {
+            continue;
+        };
+
+        let from = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+        let port = u16::from_be_bytes([tcp[0], tcp[1]]);
+        let dest_port = u16::from_be_bytes([tcp[2], tcp[3]]);
+        let ack = u32::from_be_bytes([tcp[8], tcp[9], tcp[10], tcp[11]]);
+        let flags = tcp[13];
+
+        if dest_port != source_port
+            || flags & (SYN | ACK) != SYN | ACK
+            || ack != cookie(secret, from, port).wrapping_add(1)
+        {
+            continue;
+        }
+
+        let target = Target::from(SocketAddr::new(IpAddr::V4(from), port));
+        if tx.blocking_send(target).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sequence number derived from the destination, so SYN-ACKs can be validated statelessly
+fn cookie(secret: u32, ip: Ipv4Addr, port: u16) -> u32 {
+    let mut hash = secret ^ u32::from(ip);
+    hash = hash.wrapping_mul(0x9E3779B1) ^ port as u32;
+    hash.wrapping_mul(0x85EBCA6B) ^ (hash >> 16)
+}
+
+/// 20 byte TCP header with SYN set; the kernel adds the IPv4 header
+fn syn_segment(source: Ipv4Addr, dest: Ipv4Addr, source_port: u16, port: u16, seq: u32) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(20);
+    segment.extend(source_port.to_be_bytes());
+    segment.extend(port.to_be_bytes());
+    segment.extend(seq.to_be_bytes());
+    segment.extend(0u32.to_be_bytes()); // Ack
+    segment.push(5 << 4); // Data offset
+    segment.push(SYN);
+    segment.extend(1024u16.to_be_bytes()); // Window
+    segment.extend(0u16.to_be_bytes()); // Checksum
+    segment.extend(0u16.to_be_bytes()); // Urgent pointer
+
+    let checksum = tcp_checksum(source, dest, &segment);
+    segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+    segment
+}
+
+/// One's complement sum over the pseudo header and segment
+fn tcp_checksum(source: Ipv4Addr, dest: Ipv4Addr, segment: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(12 + segment.len());
+    pseudo.extend(source.octets());
+    pseudo.extend(dest.octets());
+    pseudo.push(0);
+    pseudo.push(6); // TCP
+    pseudo.extend((segment.len() as u16).to_be_bytes());
+    pseudo.extend(segment);
+
+    let mut sum: u32 = pseudo
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Source address the kernel would pick for outgoing traffic. Connecting UDP sends nothing.
+fn local_ipv4() -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("192.0.2.1:9")?;
+
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(io::Error::other("No IPv4 source address")),
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::utils::unspecified_addr;
+
+/// https://wiki.vg/Query
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+
+/// Type 0x09
+const HANDSHAKE: u8 = 0x09;
+/// Type 0x00
+const STAT: u8 = 0x00;
+
+/// `splitnum\0\x80\0`
+const KV_PADDING: usize = 11;
+/// `\x01player_\0\0`
+const PLAYERS_PADDING: usize = 10;
+
+/// Handshake + Full stat. Query usually listens on the game port over UDP.
+pub async fn get_query(addr: SocketAddr) -> Result<Value> {
+    let socket = UdpSocket::bind(unspecified_addr(&addr)).await?;
+    socket.connect(addr).await?;
+
+    let session_id = rand::random::<i32>() & 0x0F0F0F0F;
+
+    let mut handshake = MAGIC.to_vec();
+    handshake.push(HANDSHAKE);
+    handshake.extend(session_id.to_be_bytes());
+
+    let response = request(&socket, &handshake).await?;
+    let token = parse_challenge(&response, session_id)?;
+
+    let mut full_stat = MAGIC.to_vec();
+    full_stat.push(STAT);
+    full_stat.extend(session_id.to_be_bytes());
+    full_stat.extend(token.to_be_bytes());
+    full_stat.extend([0x00; 4]);
+
+    let response = request(&socket, &full_stat).await?;
+
+    parse_full_stat(&response, session_id)
+}
+
+async fn request(socket: &UdpSocket, packet: &[u8]) -> Result<Vec<u8>> {
+    socket.send(packet).await?;
+
+    let mut buf = vec![0; 4096];
+    let len = timeout(Duration::from_secs(3), socket.recv(&mut buf))
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "No query response"))??;
+    buf.truncate(len);
+
+    Ok(buf)
+}
+
+/// Type(1) + SessionID(4) + Body
+fn check_header(response: &[u8], kind: u8, session_id: i32) -> Result<&[u8]> {
+    if response.len() < 5 || response[0] != kind || response[1..5] != session_id.to_be_bytes() {
+        return Err(Error::new(ErrorKind::InvalidData, "Bad query header"));
+    }
+
+    Ok(&response[5..])
+}
+
+/// Challenge token is sent as a null-terminated decimal string
+fn parse_challenge(response: &[u8], session_id: i32) -> Result<i32> {
+    let body = check_header(response, HANDSHAKE, session_id)?;
+    let token = String::from_utf8_lossy(body);
+
+    token
+        .trim_end_matches('\0')
+        .parse::<i64>()
+        .map(|t| t as i32)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Padding + K/V section + Padding + player names
+fn parse_full_stat(response: &[u8], session_id: i32) -> Result<Value> {
+    let body = check_header(response, STAT, session_id)?;
+    let body = body
+        .get(KV_PADDING..)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Truncated full stat"))?;
+
+    // Offsets are counted on the raw bytes, lossy decoding can change the length
+    let mut strings = body.split(|b| *b == 0);
+
+    let mut info = json!({});
+    let mut kv_len = 0;
+    while let Some(key) = strings.next() {
+        kv_len += key.len() + 1;
+        if key.is_empty() {
+            break;
+        }
+
+        let value = strings.next().unwrap_or_default();
+        kv_len += value.len() + 1;
+        info[String::from_utf8_lossy(key).to_string()] = json!(String::from_utf8_lossy(value));
+    }
+
+    let players: Vec<String> = body
+        .get(kv_len + PLAYERS_PADDING..)
+        .unwrap_or_default()
+        .split(|b| *b == 0)
+        .take_while(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect();
+
+    let (software, plugins) = parse_plugins(info["plugins"].as_str().unwrap_or(""));
+    let number = |key: &str| {
+        info[key]
+            .as_str()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(-1)
+    };
+
+    Ok(json!({
+        "motd": info["hostname"],
+        "gameType": info["gametype"],
+        "gameId": info["game_id"],
+        "version": info["version"],
+        "software": software,
+        "plugins": plugins,
+        "map": info["map"],
+        "online": number("numplayers"),
+        "max": number("maxplayers"),
+        "hostPort": number("hostport"),
+        "hostIp": info["hostip"],
+        "players": players
+    }))
+}
+
+/// `CraftBukkit on Bukkit 1.2.5-R4.0: WorldEdit 5.3; CommandBook 2.1`
+///
+/// Vanilla sends an empty string
+fn parse_plugins(plugins: &str) -> (Option<String>, Vec<String>) {
+    match plugins.split_once(':') {
+        Some((software, list)) => (
+            Some(software.trim().to_string()),
+            list.split(';')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        ),
+        None if plugins.trim().is_empty() => (None, vec![]),
+        None => (Some(plugins.trim().to_string()), vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION_ID: i32 = 0x01020304;
+
+    /// Full stat of a Paper server with two players online
+    fn full_stat(kv: &[u8], players: &[u8]) -> Vec<u8> {
+        let mut response = vec![STAT];
+        response.extend(SESSION_ID.to_be_bytes());
+        response.extend(b"splitnum\x00\x80\x00");
+        response.extend(kv);
+        response.extend(b"\x01player_\x00\x00");
+        response.extend(players);
+        response
+    }
+
+    const KV: &[u8] = b"hostname\x00A Minecraft Server\x00gametype\x00SMP\x00game_id\x00MINECRAFT\x00\
+        version\x001.20.4\x00plugins\x00Paper on 1.20.4-R0.1-SNAPSHOT: WorldEdit 7.2.19; LuckPerms 5.4.117\x00\
+        map\x00world\x00numplayers\x002\x00maxplayers\x0020\x00hostport\x0025565\x00hostip\x00127.0.0.1\x00\x00";
+
+    #[test]
+    fn parses_full_stat() {
+        let stat = parse_full_stat(&full_stat(KV, b"Steve\x00Alex\x00\x00"), SESSION_ID).unwrap();
+
+        assert_eq!(stat["motd"], "A Minecraft Server");
+        assert_eq!(stat["gameType"], "SMP");
+        assert_eq!(stat["gameId"], "MINECRAFT");
+        assert_eq!(stat["version"], "1.20.4");
+        assert_eq!(stat["software"], "Paper on 1.20.4-R0.1-SNAPSHOT");
+        assert_eq!(
+            stat["plugins"],
+            json!(["WorldEdit 7.2.19", "LuckPerms 5.4.117"])
+        );
+        assert_eq!(stat["map"], "world");
+        assert_eq!(stat["online"], 2);
+        assert_eq!(stat["max"], 20);
+        assert_eq!(stat["hostPort"], 25565);
+        assert_eq!(stat["hostIp"], "127.0.0.1");
+        assert_eq!(stat["players"], json!(["Steve", "Alex"]));
+    }
+
+    #[test]
+    fn vanilla_has_no_plugins() {
+        let kv = b"hostname\x00motd\x00plugins\x00\x00numplayers\x000\x00\x00";
+        let stat = parse_full_stat(&full_stat(kv, b"\x00"), SESSION_ID).unwrap();
+
+        assert_eq!(stat["software"], Value::Null);
+        assert_eq!(stat["plugins"], json!([]));
+        assert_eq!(stat["online"], 0);
+        assert_eq!(stat["max"], -1);
+        assert_eq!(stat["players"], json!([]));
+    }
+
+    #[test]
+    fn invalid_utf8_keeps_player_offsets() {
+        let kv = b"hostname\x00caf\xe9 \xff\xfe\x00numplayers\x001\x00\x00";
+        let stat = parse_full_stat(&full_stat(kv, b"Steve\x00\x00"), SESSION_ID).unwrap();
+
+        assert_eq!(stat["motd"], "caf\u{fffd} \u{fffd}\u{fffd}");
+        assert_eq!(stat["players"], json!(["Steve"]));
+    }
+
+    #[test]
+    fn truncated_full_stat() {
+        let response = full_stat(KV, b"Steve\x00Alex\x00\x00");
+
+        assert!(parse_full_stat(&response[..4], SESSION_ID).is_err());
+        assert!(parse_full_stat(&response[..10], SESSION_ID).is_err());
+
+        // Cut inside the K/V section: what arrived is kept, no players
+        let stat = parse_full_stat(&response[..60], SESSION_ID).unwrap();
+        assert_eq!(stat["motd"], "A Minecraft Server");
+        assert_eq!(stat["online"], -1);
+        assert_eq!(stat["players"], json!([]));
+
+        // Cut inside the player list
+        let stat = parse_full_stat(&response[..response.len() - 4], SESSION_ID).unwrap();
+        assert_eq!(stat["players"], json!(["Steve", "Al"]));
+    }
+
+    #[test]
+    fn rejects_other_sessions_and_packets() {
+        let response = full_stat(KV, b"\x00");
+
+        assert!(parse_full_stat(&response, SESSION_ID + 1).is_err());
+
+        let mut handshake = response.clone();
+        handshake[0] = HANDSHAKE;
+        assert!(parse_full_stat(&handshake, SESSION_ID).is_err());
+    }
+
+    #[test]
+    fn parses_challenge() {
+        let mut response = vec![HANDSHAKE];
+        response.extend(SESSION_ID.to_be_bytes());
+        response.extend(b"9513307\x00");
+        assert_eq!(parse_challenge(&response, SESSION_ID).unwrap(), 9513307);
+
+        // Tokens above i32::MAX wrap like Java's Integer
+        let mut large = response[..5].to_vec();
+        large.extend(b"4294967295\x00");
+        assert_eq!(parse_challenge(&large, SESSION_ID).unwrap(), -1);
+
+        let mut garbage = response[..5].to_vec();
+        garbage.extend(b"token\x00");
+        assert!(parse_challenge(&garbage, SESSION_ID).is_err());
+        assert!(parse_challenge(&response[..3], SESSION_ID).is_err());
+        assert!(parse_challenge(&response, SESSION_ID + 1).is_err());
+    }
+
+    #[test]
+    fn splits_plugins() {
+        assert_eq!(parse_plugins(""), (None, vec![]));
+        assert_eq!(
+            parse_plugins("CraftBukkit on Bukkit 1.2.5-R4.0: WorldEdit 5.3; CommandBook 2.1"),
+            (
+                Some("CraftBukkit on Bukkit 1.2.5-R4.0".to_string()),
+                vec!["WorldEdit 5.3".to_string(), "CommandBook 2.1".to_string()]
+            )
+        );
+        assert_eq!(parse_plugins("Paper"), (Some("Paper".to_string()), vec![]));
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    env,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use ipnet::IpNet;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{sleep, Duration},
+};
+
+/// Shared politeness controls: one token bucket per probe kind plus a per-subnet
+/// concurrency cap, so no single hosting provider sees all of our traffic at once.
+pub struct RateLimiter {
+    pub connect: TokenBucket,
+    pub status: TokenBucket,
+    pub login: TokenBucket,
+    pub subnets: Arc<SubnetLimiter>,
+}
+
+impl RateLimiter {
+    /// `RATE_CONNECT`, `RATE_STATUS`, `RATE_LOGIN` in probes per second (0 = unlimited),
+    /// `SUBNET_CONCURRENCY` per /24 (v4) or /48 (v6)
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: &str| -> u32 {
+            env::var(name)
+                .unwrap_or(default.to_string())
+                .parse()
+                .unwrap()
+        };
+
+        RateLimiter {
+            connect: TokenBucket::new(var("RATE_CONNECT", "0")),
+            status: TokenBucket::new(var("RATE_STATUS", "0")),
+            login: TokenBucket::new(var("RATE_LOGIN", "0")),
+            subnets: Arc::new(SubnetLimiter::new(var("SUBNET_CONCURRENCY", "16") as usize)),
+        }
+    }
+}
+
+/// Refills `rate` tokens per second, bursting up to one second's worth
+pub struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            state: Mutex::new((rate as f64, Instant::now())),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        if self.rate == 0.0 {
+            return;
+        }
+
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let (tokens, last) = &mut *state;
+
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate)
+                    .min(self.rate.max(1.0));
+                *last = now;
+
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - *tokens) / self.rate)
+            };
+
+            sleep(wait).await;
+        }
+    }
+}
+
+/// A semaphore per busy subnet, removed once nobody holds or waits for it
+pub struct SubnetLimiter {
+    max: usize,
+    active: Mutex<HashMap<IpNet, Arc<Semaphore>>>,
+}
+
+/// Frees the subnet slot on drop. The permit drops before the `Subnet` that
+/// checks whether the subnet went idle.
+pub struct SubnetPermit {
+    _slot: Option<(OwnedSemaphorePermit, Subnet)>,
+}
+
+/// Holds a reference to the subnet's semaphore, waiting or not
+struct Subnet {
+    limiter: Arc<SubnetLimiter>,
+    net: IpNet,
+    semaphore: Arc<Semaphore>,
+}
+
+impl SubnetLimiter {
+    /// 0 = unlimited
+    pub fn new(max: usize) -> Self {
+        SubnetLimiter {
+            max,
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn subnet(ip: IpAddr) -> IpNet {
+        let prefix = match ip {
+            IpAddr::V4(_) => 24,
+            IpAddr::V6(_) => 48,
+        };
+
+        IpNet::new(ip, prefix).unwrap().trunc()
+    }
+
+    pub async fn acquire(self: &Arc<Self>, ip: IpAddr) -> SubnetPermit {
+        if self.max == 0 {
+            return SubnetPermit { _slot: None };
+        }
+
+        let net = Self::subnet(ip);
+        let semaphore = self
+            .active
+            .lock()
+            .unwrap()
+            .entry(net)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max)))
+            .clone();
+        // Cleans up after a cancelled wait too
+        let subnet = Subnet {
+            limiter: self.clone(),
+            net,
+            semaphore,
+        };
+
+        // Never closed
+        let permit = subnet.semaphore.clone().acquire_owned().await.unwrap();
+        SubnetPermit {
+            _slot: Some((permit, subnet)),
+        }
+    }
+}
+
+impl Drop for Subnet {
+    fn drop(&mut self) {
+        // One reference in the map, one here: nobody else is using the subnet
+        let mut active = self.limiter.active.lock().unwrap();
+        if active
+            .get(&self.net)
+            .is_some_and(|semaphore| Arc::strong_count(semaphore) == 2)
+        {
+            active.remove(&self.net);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn busy_subnets(limiter: &SubnetLimiter) -> usize {
+        limiter.active.lock().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn caps_each_subnet() {
+        let limiter = Arc::new(SubnetLimiter::new(2));
+
+        let a = limiter.acquire(ip("1.2.3.4")).await;
+        let _b = limiter.acquire(ip("1.2.3.5")).await;
+        // Another /24 isn't affected
+        let _other = limiter.acquire(ip("1.2.4.4")).await;
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(ip("1.2.3.6")).await }
+        });
+        sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(a);
+        let _c = waiting.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn removes_idle_subnets() {
+        let limiter = Arc::new(SubnetLimiter::new(1));
+
+        let first = limiter.acquire(ip("1.2.3.4")).await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { drop(limiter.acquire(ip("1.2.3.5")).await) }
+        });
+        sleep(Duration::from_millis(50)).await;
+        let _v6 = limiter.acquire(ip("2001:db8::1")).await;
+        assert_eq!(busy_subnets(&limiter), 2);
+
+        // Still wanted by the waiter until it's done
+        drop(first);
+        waiting.await.unwrap();
+        assert_eq!(busy_subnets(&limiter), 1);
+
+        // Giving up on the wait doesn't leave the subnet behind
+        let held = limiter.acquire(ip("1.2.3.4")).await;
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(10), limiter.acquire(ip("1.2.3.5"))).await;
+        assert!(cancelled.is_err());
+        drop(held);
+        assert_eq!(busy_subnets(&limiter), 1);
+    }
+}
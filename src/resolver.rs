@@ -0,0 +1,224 @@
+use std::{
+    io::{Error, Result},
+    net::{IpAddr, SocketAddr},
+};
+
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+
+use crate::target::Target;
+
+pub trait Resolver: Send + Sync {
+    /// (target, port) pairs ordered by priority
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>>;
+
+    /// A and AAAA records
+    async fn lookup_ip(&self, host: &str) -> Result<Vec<IpAddr>>;
+}
+
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsResolver {
+    pub fn new() -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+
+        DnsResolver { resolver }
+    }
+}
+
+impl Resolver for DnsResolver {
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>> {
+        let lookup = self.resolver.srv_lookup(name).await.map_err(Error::other)?;
+
+        let mut records: Vec<_> = lookup.iter().collect();
+        records.sort_by_key(|r| (r.priority(), u16::MAX - r.weight()));
+
+        Ok(records
+            .into_iter()
+            .map(|r| {
+                (
+                    r.target().to_utf8().trim_end_matches('.').to_string(),
+                    r.port(),
+                )
+            })
+            .collect())
+    }
+
+    async fn lookup_ip(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let lookup = self.resolver.lookup_ip(host).await.map_err(Error::other)?;
+
+        Ok(lookup.iter().collect())
+    }
+}
+
+pub struct Resolved {
+    pub target: Target,
+    pub srv: bool,
+}
+
+/// `host` or `host:port`. `_minecraft._tcp` SRV records win over A/AAAA, like the vanilla client.
+pub async fn resolve<R: Resolver>(resolver: &R, host: &str) -> Result<Vec<Resolved>> {
+    if let Ok(addr) = host.parse::<SocketAddr>() {
+        return Ok(vec![Resolved {
+            target: Target::from(addr),
+            srv: false,
+        }]);
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![Resolved {
+            target: Target::from(SocketAddr::new(ip, 25565)),
+            srv: false,
+        }]);
+    }
+
+    let (host, port) = match host.split_once(':') {
+        Some((host, port)) => (
+            host,
+            Some(
+                port.parse::<u16>()
+                    .map_err(|e| Error::new(std::io::ErrorKind::InvalidInput, e))?,
+            ),
+        ),
+        None => (host, None),
+    };
+
+    let mut results = Vec::new();
+
+    // An explicit port bypasses SRV
+    if port.is_none() {
+        let srv = resolver
+            .lookup_srv(&format!("_minecraft._tcp.{}", host))
+            .await
+            .unwrap_or_default();
+
+        for (srv_target, srv_port) in srv {
+            for ip in resolver.lookup_ip(&srv_target).await.unwrap_or_default() {
+                results.push(Resolved {
+                    target: Target::new(SocketAddr::new(ip, srv_port), Some(host.to_string())),
+                    srv: true,
+                });
+            }
+        }
+    }
+
+    if results.is_empty() {
+        for ip in resolver.lookup_ip(host).await? {
+            results.push(Resolved {
+                target: Target::new(
+                    SocketAddr::new(ip, port.unwrap_or(25565)),
+                    Some(host.to_string()),
+                ),
+                srv: false,
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Fixed records instead of DNS
+    #[derive(Default)]
+    struct StaticResolver {
+        srv: HashMap<String, Vec<(String, u16)>>,
+        ip: HashMap<String, Vec<IpAddr>>,
+    }
+
+    impl Resolver for StaticResolver {
+        async fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>> {
+            Ok(self.srv.get(name).cloned().unwrap_or_default())
+        }
+
+        async fn lookup_ip(&self, host: &str) -> Result<Vec<IpAddr>> {
+            Ok(self.ip.get(host).cloned().unwrap_or_default())
+        }
+    }
+
+    fn resolver() -> StaticResolver {
+        let mut resolver = StaticResolver::default();
+        resolver.srv.insert(
+            "_minecraft._tcp.example.com".to_string(),
+            vec![("mc.example.com".to_string(), 25570)],
+        );
+        resolver.ip.insert(
+            "mc.example.com".to_string(),
+            vec!["10.0.0.2".parse().unwrap()],
+        );
+        resolver
+            .ip
+            .insert("example.com".to_string(), vec!["10.0.0.1".parse().unwrap()]);
+        resolver.ip.insert(
+            "plain.example.com".to_string(),
+            vec!["10.0.0.3".parse().unwrap(), "::3".parse().unwrap()],
+        );
+        resolver
+    }
+
+    fn addrs(resolved: &[Resolved]) -> Vec<String> {
+        resolved.iter().map(|r| r.target.addr.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn srv_wins_over_a_records() {
+        let resolved = resolve(&resolver(), "example.com").await.unwrap();
+
+        assert_eq!(addrs(&resolved), ["10.0.0.2:25570"]);
+        assert!(resolved[0].srv);
+        assert_eq!(resolved[0].target.hostname.as_deref(), Some("example.com"));
+    }
+
+    #[tokio::test]
+    async fn explicit_port_skips_srv() {
+        let resolved = resolve(&resolver(), "example.com:25600").await.unwrap();
+
+        assert_eq!(addrs(&resolved), ["10.0.0.1:25600"]);
+        assert!(!resolved[0].srv);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_a_and_aaaa() {
+        let resolved = resolve(&resolver(), "plain.example.com").await.unwrap();
+
+        assert_eq!(addrs(&resolved), ["10.0.0.3:25565", "[::3]:25565"]);
+        assert!(resolved.iter().all(|r| !r.srv));
+    }
+
+    #[tokio::test]
+    async fn ip_literals_skip_dns() {
+        let resolved = resolve(&resolver(), "1.2.3.4").await.unwrap();
+        assert_eq!(addrs(&resolved), ["1.2.3.4:25565"]);
+        assert_eq!(resolved[0].target.hostname, None);
+
+        let resolved = resolve(&resolver(), "1.2.3.4:25570").await.unwrap();
+        assert_eq!(addrs(&resolved), ["1.2.3.4:25570"]);
+
+        let resolved = resolve(&resolver(), "[::1]:25570").await.unwrap();
+        assert_eq!(addrs(&resolved), ["[::1]:25570"]);
+
+        let resolved = resolve(&resolver(), "::1").await.unwrap();
+        assert_eq!(addrs(&resolved), ["[::1]:25565"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_port_is_an_error() {
+        assert!(resolve(&resolver(), "example.com:99999").await.is_err());
+        assert!(resolve(&resolver(), "example.com:abc").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_host_resolves_to_nothing() {
+        let resolved = resolve(&resolver(), "missing.example.com").await.unwrap();
+        assert!(resolved.is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+use std::{fmt::Display, net::SocketAddr};
+
+/// Address to connect to plus the hostname to put into the Handshake, if it was resolved from one
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub addr: SocketAddr,
+    pub hostname: Option<String>,
+}
+
+impl Target {
+    pub fn new(addr: SocketAddr, hostname: Option<String>) -> Self {
+        Target { addr, hostname }
+    }
+
+    /// Virtual-host proxies route on this, so prefer the original hostname
+    pub fn server_address(&self) -> String {
+        match &self.hostname {
+            Some(hostname) => hostname.clone(),
+            None => self.addr.ip().to_string(),
+        }
+    }
+}
+
+impl From<SocketAddr> for Target {
+    fn from(addr: SocketAddr) -> Self {
+        Target::new(addr, None)
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.hostname {
+            Some(hostname) => write!(f, "{} -> {}", hostname, self.addr),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
@@ -1,66 +1,12 @@
 use std::{
-    net::{IpAddr, SocketAddr},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     time::Duration,
 };
 
 use rand::Rng;
-use serde_json::Value;
 use tokio::{net::TcpSocket, time::timeout};
 
-#[allow(dead_code)]
-pub struct StatusWrap {
-    pub ip: String,
-    pub port: String,
-    pub license: i64,
-    pub version: String,
-    pub description: String,
-    pub online: i64,
-    pub max_online: i64,
-}
-
-impl StatusWrap {
-    pub fn from_value(value: &Value) -> Self {
-        let ip = value["ip"].as_str().unwrap_or("err").to_string();
-        let port = value["port"].as_str().unwrap_or("err").to_string();
-        let license = value["license"].as_i64().unwrap_or(-1);
-        let version = value["status"]["version"]["name"]
-            .as_str()
-            .unwrap_or("err")
-            .to_string();
-        let description = {
-            let mut result = String::new();
-
-            if let Some(extra) = value["status"]["description"]["extra"].as_array() {
-                for element in extra {
-                    if let Some(str) = element.as_str() {
-                        result += str;
-                    } else if let Some(str) = element["text"].as_str() {
-                        result += str;
-                    }
-                }
-            } else if let Some(text) = value["status"]["description"].as_str() {
-                result += text;
-            } else if let Some(text) = value["status"]["description"]["text"].as_str() {
-                result += text;
-            }
-
-            result
-        };
-        let online = value["status"]["players"]["online"].as_i64().unwrap_or(-1);
-        let max_online = value["status"]["players"]["max"].as_i64().unwrap_or(-1);
-
-        StatusWrap {
-            ip,
-            port,
-            license,
-            version,
-            description,
-            online,
-            max_online,
-        }
-    }
-}
-
 pub fn get_random_ip() -> IpAddr {
     let mut rng = rand::thread_rng();
 
@@ -75,21 +21,33 @@ pub fn get_random_ip() -> IpAddr {
     ip.parse().unwrap()
 }
 
-pub async fn check_port_open(ip: IpAddr, port: u16) -> bool {
-    let socket = TcpSocket::new_v4().unwrap();
+/// TCP socket of the same address family as `addr`
+pub fn new_socket(addr: &SocketAddr) -> io::Result<TcpSocket> {
+    match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+}
 
-    if let Ok(stream) = timeout(
-        Duration::from_secs(3),
-        socket.connect(SocketAddr::new(ip, port)),
-    )
-    .await
-    {
-        if let Ok(_) = stream {
-            true
-        } else {
-            false
-        }
-    } else {
-        false
+/// Wildcard local address of the same family as `addr`, for UDP sockets
+pub fn unspecified_addr(addr: &SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
     }
 }
+
+pub async fn check_port_open(ip: IpAddr, port: u16) -> bool {
+    let Ok(socket) = new_socket(&SocketAddr::new(ip, port)) else {
+        return false;
+    };
+
+    matches!(
+        timeout(
+            Duration::from_secs(3),
+            socket.connect(SocketAddr::new(ip, port)),
+        )
+        .await,
+        Ok(Ok(_))
+    )
+}